@@ -1,10 +1,11 @@
 use std::collections::{HashMap, HashSet, BTreeSet};
 use std::path::{Path, PathBuf};
-use std::io::{Write};
 use log::{error, warn, info, debug};
 use failure::{Error, ResultExt, Fail};
-use sd3::{CmpdDit, Mifc};
-use crate::{output};
+use sd3::{CmpdDit, ColumnAliases, ExclusionRules, Layout, Mifc, MifcWriter, MolecularWeights, MolWeightError, Severity};
+use sd3::io::Located;
+use units::Unit;
+use crate::output::{MifcOutput};
 
 /// A HashSet that contains the various data points with special chip ids 
 /// whose data are duplicated to any chips that share the same group.  
@@ -63,6 +64,48 @@ impl<'opt> GroupInfo<'opt> {
     }
 }
 
+/// An optional unit to convert every row's Value into, with the molecular
+/// weights needed to go between molar and mass concentrations
+#[derive(Debug)]
+struct ValueConversion {
+    to: Option<Unit>,
+    weights: MolecularWeights,
+}
+
+impl ValueConversion {
+    fn from_options(options: &crate::Opt) -> Result<Self, Error> {
+        let weights = match options.mw_file.as_ref() {
+            Some(path) => {
+                let weights = MolecularWeights::from_path(path)
+                    .context(format!("loading molecular weights from <{:?}>", path))?;
+                info!("loaded {} molecular weight(s) from {:?}", weights.len(), path);
+                weights
+            },
+            None => MolecularWeights::new(),
+        };
+
+        Ok(ValueConversion { to: options.convert_to, weights })
+    }
+
+    /// Convert the row's value, if a unit was chosen. Rows whose target has no
+    /// molecular weight are reported and left in their original unit.
+    fn apply(&self, mifc: &mut Mifc) -> Result<(), ConversionErr> {
+        let to = match self.to {
+            Some(to) => to,
+            None => return Ok(()),
+        };
+
+        match mifc.convert_value(to, &self.weights) {
+            Err(MolWeightError::MissingTarget(target)) => {
+                warn!("no molecular weight for target <{}>; leaving chip <{}> value in {}",
+                    target, mifc.id, mifc.value_unit.as_ref().map_or("", String::as_str));
+                Ok(())
+            },
+            r => r.context(format!("converting value to {}", to)).map_err(recoverable_err),
+        }
+    }
+}
+
 /// Helper error type to handle recoverable or unrecoverable de/serialization errors
 #[derive(Debug, Fail)]
 enum ConversionErr {
@@ -75,14 +118,29 @@ enum ConversionErr {
 fn recoverable_err<E: Into<Error>>(e: E) -> ConversionErr {ConversionErr::Recoverable(e.into())}
 fn unrecoverable_err<E: Into<Error>>(e: E) -> ConversionErr {ConversionErr::NotRecoverable(e.into())}
 
-/// The key function that converts an `Iterator` of paths to CSV CMPD MIFC files into proper output MIFC files 
+/// The key function that converts an `Iterator` of paths to CMPD MIFC files (CSV or workbooks) into proper output MIFC files 
 pub fn cmpd_csv_to_mifc<'i>(files: impl Iterator<Item = PathBuf> + 'i, options: &crate::Opt) -> Result<(), Error> 
 {
     let other_terms = options.other_terms.iter().map(String::as_str);
     let prop_groups = PropGroups::new(other_terms);
+    let conversion = ValueConversion::from_options(options)?;
+    let flag_rules = match options.flag_rules.as_ref() {
+        Some(path) => ExclusionRules::from_path(path)
+            .context(format!("loading flag rules from <{:?}>", path))?,
+        None => ExclusionRules::default(),
+    };
+    let column_aliases = match options.column_aliases.as_ref() {
+        Some(path) => {
+            let aliases = ColumnAliases::from_path(path)
+                .context(format!("loading column aliases from <{:?}>", path))?;
+            info!("loaded {} column alias(es) from {:?}", aliases.len(), path);
+            aliases
+        },
+        None => ColumnAliases::new(),
+    };
 
     for path in files {
-        match convert_file(&path, options, &prop_groups) {
+        match convert_file(&path, options, &prop_groups, &conversion, &flag_rules, &column_aliases) {
             Err(ConversionErr::Recoverable(e)) => {
                 error!("skipping file <{:?}> due to:", &path);
                 errlog::print_chain(&e);
@@ -104,7 +162,10 @@ pub fn cmpd_csv_to_mifc<'i>(files: impl Iterator<Item = PathBuf> + 'i, options:
 fn convert_file<'opt, 'f>(
     path: &'f Path, 
     options: &'opt crate::Opt, 
-    propgrps: &'f PropGroups<'opt>
+    propgrps: &'f PropGroups<'opt>,
+    conversion: &'f ValueConversion,
+    flag_rules: &'f ExclusionRules,
+    column_aliases: &'f ColumnAliases,
 ) -> Result<(), ConversionErr>
 {
     let append_str = options.append.as_ref().map(|s| s.as_str()).unwrap_or("mifc");
@@ -113,52 +174,89 @@ fn convert_file<'opt, 'f>(
 
     info!("reading {:?}", &path);
 
-    let mut csv_rdr = csv::Reader::from_path(&path)
+    let input = sd3::io::open(&path)
         .context(format!("couldn't open input for reading; skipping file <{:?}>", &path))
-        .map_err(recoverable_err)?;
+        .map_err(recoverable_err)?
+        .with_aliases(column_aliases);
     
     let mut groups = ChipGroups::new();
     // TODO: return output file path as well? for cleanup?
-    let wtr = output::get_output_wtr(use_stdout, &output_dir, &path, &append_str)
+    let mut wtr = MifcOutput::open(options.format, use_stdout, &output_dir, &path, &append_str)
         .context(format!("couldn't open output; skipping file <{:?}>", &path))
         .map_err(recoverable_err)?;
-    let mut wtr = csv::Writer::from_writer(wtr);
+    let default_sheet = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
 
-    for result in csv_rdr.deserialize() {
-        let record: CmpdDit = match result {
-            Ok(r)  => r,
-            Err(e) => {
-                warn!("couldn't deserialize row");
-                errlog::warn_chain(&e.into());
-                continue;
-            }
+    // every sheet of a workbook is converted into the same CSV output, or into its own sheet of an XLSX output
+    for sheet in input.sheets() {
+        let sheet = sheet.map_err(recoverable_err)?;
+        let headers = sheet.headers();
+        let sheet_name = sheet.name().unwrap_or(&default_sheet);
+        if headers.iter().all(|h| h.trim().is_empty()) {
+            debug!("skipping empty sheet <{}>", sheet_name);
+            continue;
+        }
+        let place = match sheet.name() {
+            Some(name) => format!("sheet <{}> of {:?}", name, &path),
+            None => format!("{:?}", &path),
         };
-
-        match write_record(record, &mut wtr, &propgrps, &mut groups) {
-            Err(ConversionErr::Recoverable(e)) => {
-                warn!("skipping row in <{:?}>", &path);
-                errlog::warn_chain(&e);
+        for column in sheet.columns().renamed() {
+            info!("{}: {}", place, column);
+        }
+        match sd3::check_layout(&headers, Layout::CmpdDit) {
+            Ok(report) => if !report.unexpected.is_empty() {
+                info!("{} has extra column(s), which are written after the MIFC columns: {}", place, report.unexpected.join(", "));
+            },
+            Err(e) => {
+                warn!("skipping {}: {}", place, e);
                 continue;
             },
-            Err(ConversionErr::NotRecoverable(e)) => {
-                return Err(ConversionErr::Recoverable(e));
-            }
-            _ => (),
-        };
+        }
+        let rows = sheet.records_with_extra::<CmpdDit>().map_err(recoverable_err)?;
+        wtr.start_sheet(sheet_name).map_err(recoverable_err)?;
+
+        for result in rows {
+            let Located { location, record } = match result {
+                Ok(r)  => r,
+                Err(e) => {
+                    warn!("couldn't deserialize row");
+                    errlog::warn_chain(&e.into());
+                    continue;
+                }
+            };
+
+            match write_record(record, &mut wtr, &propgrps, &mut groups, conversion, flag_rules) {
+                Err(ConversionErr::Recoverable(e)) => {
+                    warn!("skipping {}", &location);
+                    errlog::warn_chain(&e);
+                    continue;
+                },
+                Err(ConversionErr::NotRecoverable(e)) => {
+                    return Err(ConversionErr::Recoverable(e));
+                }
+                _ => (),
+            };
+        }
+        // sheets with their own output only propagate their own data points
+        if wtr.by_sheet() {
+            write_prop_rows(&mut wtr, std::mem::take(&mut groups)).map_err(recoverable_err)?;
+        }
     }
     // propagate various other collected data points, if needed
     write_prop_rows(&mut wtr, groups).map_err(recoverable_err)?;
+    wtr.finish().map_err(recoverable_err)?;
 
     Ok(())
 }
 
 /// Write out one record from the input CSV file to the ouput, 
 /// and save any important info about that record into the `&mut ChipGroups` struct  
-fn write_record<'opt: 'f, 'f: 'r, 'r, W: Write>(
+fn write_record<'opt: 'f, 'f: 'r, 'r>(
     record: CmpdDit, 
-    output: &'r mut csv::Writer<W>,
+    output: &'r mut dyn MifcWriter,
     prop_grp: &'f PropGroups<'opt>, 
-    chip_grps: &'r mut ChipGroups<'opt>
+    chip_grps: &'r mut ChipGroups<'opt>,
+    conversion: &'f ValueConversion,
+    flag_rules: &'f ExclusionRules,
 ) -> Result<(), ConversionErr> 
 {
     let r_group = record.group();
@@ -172,20 +270,24 @@ fn write_record<'opt: 'f, 'f: 'r, 'r, W: Write>(
     /* A chip with an id of "stock"/"reservoir"/etc. means that the info for 
     ** that chip is meant to be applied to all chips in the a group */
     if let Some(group) = prop_grp.get(&r_id) {
-        let prop_mifc = record.into_mifc()
+        let mut prop_mifc = record.into_mifc(flag_rules)
             .context("converting a propagating group into MIFC format")
             .map_err(unrecoverable_err)?;
+        report_issues(&prop_mifc);
+        conversion.apply(&mut prop_mifc)?;
         
         group_info.propagate(group, prop_mifc); 
     } 
     /* otherwise, add chip id to the group map and convert the row */
     else {
         group_info.add_chip(&r_id);
-        let mifc = record.into_mifc()
+        let mut mifc = record.into_mifc(flag_rules)
             .context("converting a standard row into MIFC format")
             .map_err(recoverable_err)?;
+        report_issues(&mifc);
+        conversion.apply(&mut mifc)?;
         
-        output.serialize(&mifc)
+        output.write_mifc(&mifc)
             .context("writing serialized MIFC for normal row")
             .map_err(recoverable_err)?;
     }
@@ -193,9 +295,19 @@ fn write_record<'opt: 'f, 'f: 'r, 'r, W: Write>(
     Ok(())
 }
 
+/// Log any problems found in a converted row
+fn report_issues(mifc: &Mifc) {
+    for issue in mifc.validate() {
+        match issue.severity {
+            Severity::Error => warn!("chip <{}> at {}: {}", mifc.id, mifc.time, issue),
+            Severity::Warning => info!("chip <{}> at {}: {}", mifc.id, mifc.time, issue),
+        }
+    }
+}
+
 /// Propagating any information stored from propagating rows into the output
-/// CSV file for each chip that should have that info
-fn write_prop_rows<W: Write>(output: &mut csv::Writer<W>, groups: ChipGroups) -> Result<(), Error> {
+/// for each chip that should have that info
+fn write_prop_rows(output: &mut dyn MifcWriter, groups: ChipGroups) -> Result<(), Error> {
     use std::fmt::Write;
 
     for (_group_name, group_info) in groups.into_iter() {
@@ -209,7 +321,7 @@ fn write_prop_rows<W: Write>(output: &mut csv::Writer<W>, groups: ChipGroups) ->
                     // be cheeky and avoid allocations by reusing the id String
                     point.id.clear();
                     point.id.write_str(id)?;
-                    output.serialize(&point)
+                    output.write_mifc(&point)
                         .context("writing propagating chip group data to output")?; // TODO -> match
                 }
             }
@@ -3,14 +3,15 @@ mod convert;
 mod output;
 
 use structopt::{StructOpt};
-use failure::{Error, ResultExt};
-use log::{debug};
+use failure::{Error, ResultExt, bail};
+use log::{debug, info};
 use flexi_logger::{Logger, default_format};
 use std::path::PathBuf;
+use std::io;
 
 #[derive(Debug, StructOpt)]
 pub struct Opt {
-    /// Any number of input compound columnar csv files or directories containing those csv files
+    /// Any number of input compound columnar csv files or workbooks (Excel, ODS), or directories containing those files
     #[structopt(name = "INPUT", parse(from_os_str))]
     input: Vec<PathBuf>,
     /// Append to input filename for output filename; defaults to "mifc"
@@ -25,6 +26,28 @@ pub struct Opt {
     /// Output the conversion of each file to stdout instead of writing to files
     #[structopt(long = "stdout")]
     stdout: bool,
+    /// TOML or CSV file of extra unit spellings (alias -> unit) used when standardizing Result Units
+    #[structopt(long = "unit-aliases", parse(from_os_str))]
+    unit_aliases: Option<PathBuf>,
+    /// Print every known unit, with its type, aliases, and SI factor, as a table or json and exit
+    #[structopt(long = "list-units", raw(possible_values = r#"&["table", "json"]"#))]
+    list_units: Option<units::CatalogFormat>,
+    /// Convert every Value into this unit, e.g. "ng/mL"
+    #[structopt(long = "convert-to")]
+    convert_to: Option<units::Unit>,
+    /// CSV or TOML file of molecular weights (g/mol) by Target/Analyte, used by `--convert-to`
+    /// to go between molar and mass concentrations
+    #[structopt(long = "mw-file", parse(from_os_str))]
+    mw_file: Option<PathBuf>,
+    /// TOML or CSV file of which Caution Flag codes (O, W, F) exclude a row; by default, every code does
+    #[structopt(long = "flag-rules", parse(from_os_str))]
+    flag_rules: Option<PathBuf>,
+    /// TOML or CSV file of other spellings of column headers (alias -> column)
+    #[structopt(long = "column-aliases", parse(from_os_str))]
+    column_aliases: Option<PathBuf>,
+    /// Write each output as a CSV file, or as an XLSX workbook with a sheet for each input sheet
+    #[structopt(long = "format", default_value = "csv", raw(possible_values = r#"&["csv", "xlsx"]"#))]
+    format: sd3::OutputFormat,
     /// Set the verbosity level (1, 2, or 3)
     #[structopt(short = "v", parse(from_occurrences))]
     verbose: u8,
@@ -53,8 +76,20 @@ fn main() {
 fn run(opts: Opt) -> Result<(), Error> {
     let inputs = &opts.input;
     debug!("Input files: {:?}", inputs);
-    let csv_paths = proc_inputs::iter_csv_paths(inputs);
-    convert::cmpd_csv_to_mifc(csv_paths, &opts)
+    if let Some(aliases) = opts.unit_aliases.as_ref() {
+        let n = units::load_aliases(aliases)
+            .context(format!("loading unit aliases from <{:?}>", aliases))?;
+        info!("loaded {} unit alias(es) from {:?}", n, aliases);
+    }
+    if let Some(format) = opts.list_units {
+        units::write_catalog(io::stdout(), format).context("printing unit list")?;
+        return Ok(());
+    }
+    if opts.stdout && opts.format == sd3::OutputFormat::Xlsx {
+        bail!("XLSX output can't be written to stdout; use \"--format csv\" with \"--stdout\"");
+    }
+    let input_paths = proc_inputs::iter_input_paths(inputs);
+    convert::cmpd_csv_to_mifc(input_paths, &opts)
         .context("couldn't convert inputs")?;
 
     Ok(())
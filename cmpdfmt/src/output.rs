@@ -3,13 +3,71 @@ use std::fs::{self, OpenOptions};
 use std::io::{self, Write};
 use log::{debug};
 use failure::{Error, ResultExt, format_err, bail};
+use sd3::{CsvWriter, Mifc, MifcWriter, OutputFormat, WriteError, XlsxWriter};
 
-pub fn get_output_wtr(stdout: bool, dir: &Option<&Path>, name: &Path, append: &str) -> Result<Box<dyn Write>, Error> {
+/// Where the MIFC rows converted from one input file are written 
+pub enum MifcOutput {
+    /// Every sheet of the input is written into one CSV file, or stdout
+    Csv(Box<CsvWriter<Box<dyn Write>>>),
+    /// Each sheet of the input is written into its own sheet of a workbook 
+    Xlsx(Box<XlsxWriter>),
+}
+
+impl MifcOutput {
+    /// Open the output for an input file; only CSV output can go to stdout
+    pub fn open(format: OutputFormat, stdout: bool, dir: &Option<&Path>, name: &Path, append: &str) -> Result<Self, Error> {
+        Ok(match format {
+            OutputFormat::Csv => MifcOutput::Csv(Box::new(CsvWriter::new(get_output_wtr(stdout, dir, name, append)?))),
+            OutputFormat::Xlsx => {
+                let output = generate_output_filename(dir, name, append, format)?;
+                debug!("generated output: {:?}", &output);
+                MifcOutput::Xlsx(Box::new(XlsxWriter::new(output)))
+            },
+        })
+    }
+
+    /// Check if each input sheet gets a separate output sheet
+    pub fn by_sheet(&self) -> bool {
+        match self {
+            MifcOutput::Csv(_) => false,
+            MifcOutput::Xlsx(_) => true,
+        }
+    }
+
+    /// Start writing the rows of another input sheet
+    pub fn start_sheet(&mut self, name: &str) -> Result<(), WriteError> {
+        match self {
+            MifcOutput::Csv(_) => Ok(()),
+            MifcOutput::Xlsx(wtr) => wtr.add_sheet(name),
+        }
+    }
+
+    /// Finish writing the output file
+    pub fn finish(self) -> Result<(), Error> {
+        match self {
+            MifcOutput::Csv(mut wtr) => wtr.flush()?,
+            MifcOutput::Xlsx(wtr) => wtr.save()?,
+        }
+
+        Ok(())
+    }
+}
+
+impl MifcWriter for MifcOutput {
+    fn write_mifc(&mut self, row: &Mifc) -> Result<(), WriteError> {
+        match self {
+            MifcOutput::Csv(wtr) => wtr.write_mifc(row),
+            MifcOutput::Xlsx(wtr) => wtr.write_mifc(row),
+        }
+    }
+}
+
+fn get_output_wtr(stdout: bool, dir: &Option<&Path>, name: &Path, append: &str) -> Result<Box<dyn Write>, Error> {
     Ok(
         if stdout {
             Box::new(io::stdout()) as Box<dyn Write> 
         } else {
-            let output = generate_output_filename(dir, name, append)?;
+            let output = generate_output_filename(dir, name, append, OutputFormat::Csv)?;
             debug!("generated output: {:?}", &output);
             let wtr = OpenOptions::new()
                 .write(true)
@@ -23,7 +81,7 @@ pub fn get_output_wtr(stdout: bool, dir: &Option<&Path>, name: &Path, append: &s
     )
 }
 
-fn generate_output_filename(dir: &Option<&Path>, name: &Path, append: &str) -> Result<PathBuf, Error> 
+fn generate_output_filename(dir: &Option<&Path>, name: &Path, append: &str, format: OutputFormat) -> Result<PathBuf, Error> 
 {
     let filestem = name.file_stem().ok_or_else(|| format_err!("input was not a file"))?;
     let parents = name.parent();
@@ -45,7 +103,7 @@ fn generate_output_filename(dir: &Option<&Path>, name: &Path, append: &str) -> R
         output.push(filestem);
     }
 
-    output.set_extension("csv");
+    output.set_extension(format.extension());
 
     Ok(output)
 }
\ No newline at end of file
@@ -1,8 +1,10 @@
 use walkdir::WalkDir;
 use std::path::{PathBuf};
+use sd3::io::Format;
 
+/// Every CSV file or workbook that `sd3::io` can read in the inputs, or in directories in the inputs
 #[inline]
-pub fn iter_csv_paths<'i>(inputs: &'i [PathBuf]) -> impl Iterator<Item = PathBuf> + 'i {
+pub fn iter_input_paths<'i>(inputs: &'i [PathBuf]) -> impl Iterator<Item = PathBuf> + 'i {
     inputs.iter()
         .flat_map(|entry| { 
             WalkDir::new(&entry)
@@ -10,12 +12,13 @@ pub fn iter_csv_paths<'i>(inputs: &'i [PathBuf]) -> impl Iterator<Item = PathBuf
             .filter_map(|e| e.ok())
             .map(|e| e.path().to_path_buf())
         })
-        .filter(is_csv)
+        .filter(|p| Format::from_path(p).is_some())
+        .filter(is_not_excel_temp)
 }
 
+/// Check if a file is not a temporary file that Excel makes while a workbook is open
 #[inline]
-fn is_csv(file: &PathBuf) -> bool {
-    file.extension()
-        .map(|ex| ex == "csv")
-        .unwrap_or(false) 
+fn is_not_excel_temp(file: &PathBuf) -> bool {
+    !file.file_stem()
+        .is_some_and(|s| s.to_string_lossy().starts_with('~'))
 }
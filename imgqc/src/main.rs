@@ -3,8 +3,10 @@ mod qc;
 mod vocab;
 
 use anyhow::{bail, Context, Result};
+use failure::Fail;
 use flexi_logger::{default_format, Logger};
 use log::*;
+use sd3::ColumnAliases;
 use std::io::{self, BufWriter, Write};
 use std::path::{Path, PathBuf};
 use structopt::StructOpt;
@@ -13,7 +15,7 @@ use crate::vocab::*;
 
 #[derive(Debug, StructOpt)]
 struct Opts {
-    /// Path to the MIFC-I metadata excel file (adding CSV later)
+    /// Path to the MIFC-I metadata file (Excel, ODS, or CSV); every sheet is checked
     #[structopt(parse(from_os_str))]
     mifc: PathBuf,
     /// File to log QC to, or stdout if not present
@@ -25,6 +27,9 @@ struct Opts {
     /// Study chip and well info file
     #[structopt(short, long, parse(from_os_str))]
     chips: PathBuf,
+    /// TOML or CSV file of other spellings of column headers (alias -> column)
+    #[structopt(long, parse(from_os_str))]
+    column_aliases: Option<PathBuf>,
     /// Debugging Info
     #[structopt(short, long, parse(from_occurrences))]
     verbose: u8,
@@ -74,7 +79,14 @@ fn run(opts: Opts) -> Result<()> {
 
     debug!("{:#?}\n{:#?}\nimages: {}", &opts, &vocab, imgdir.display());
 
-    qc::qc_images(&opts.mifc, vocab, imgdir, output).context("running image qc")
+    let aliases = match opts.column_aliases.as_ref() {
+        Some(path) => ColumnAliases::from_path(path)
+            .map_err(Fail::compat)
+            .with_context(|| format!("loading column aliases from {}", path.display()))?,
+        None => ColumnAliases::new(),
+    };
+
+    qc::qc_images(&opts.mifc, vocab, imgdir, &aliases, output).context("running image qc")
 }
 
 fn create_file_or_stdout<P>(path: Option<P>) -> io::Result<Box<dyn Write>>
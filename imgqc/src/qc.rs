@@ -1,8 +1,9 @@
 use crate::img;
 use crate::vocab::*;
-use anyhow::{anyhow, Context, Result};
-use calamine::{self, RangeDeserializerBuilder, Reader};
-use sd3::MifcImage;
+use anyhow::{Context, Result};
+use failure::Fail;
+use sd3::io::{Located, Location};
+use sd3::{ColumnAliases, Layout, MifcField, MifcImage};
 use std::collections::{HashMap, HashSet};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
@@ -11,54 +12,77 @@ pub fn qc_images<W: Write>(
     metadata: &Path,
     vocab: VocabMaps,
     imgdir: &Path,
+    aliases: &ColumnAliases,
     mut output: W,
 ) -> Result<()> {
-    let mut wb = calamine::open_workbook_auto(metadata)
-        .context("opening input image metadata excel file")?;
-    let first_sheet = wb
-        .sheet_names()
-        .get(0)
-        .ok_or_else(|| anyhow!("No sheets in metadata workbook"))?
-        .clone();
-    let range = wb.worksheet_range(&first_sheet).unwrap()?;
-    let metadata_iter = RangeDeserializerBuilder::new().from_range(&range)?;
+    let sheets = sd3::io::open(metadata)
+        .map_err(Fail::compat)
+        .context("opening input image metadata file")?
+        .with_aliases(aliases)
+        .sheets()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Fail::compat)
+        .context("reading image metadata sheets")?;
+    let with_sheet = sheets.len() > 1;
     let summarize_row = row_summarizer(&vocab, &imgdir);
 
     // Start writing the output file
     write_output_prologue(&mut output, &metadata.to_string_lossy())?;
-    // Check the controlled vocab and image path for each row in the metadata excel file,
+    // Check the controlled vocab and image path for each row in every sheet of the metadata file,
     // while also collecting a list of image file names
     // Also, create a map of the count of file names for all images, as the image name
     // cannot be duplicated. (e.g., `file.png` and `file.mp4` are illegal)
-    let (expected_images, name_count) = metadata_iter
-        .map(Into::into)
-        .enumerate()
-        .map(|(i, record): (usize, Result<MifcImage, _>)| match record {
-            Ok(row) => summarize_row(i, &row),
-            Err(e) => RowInfo::new(
-                i,
-                None,
-                format!("* issue parsing row {} in metadata file: {}", i + 2, e),
-            ),
-        })
-        .try_fold(
-            (HashSet::new(), HashMap::new()),
-            |(mut imgs, mut counts), info| -> io::Result<_> {
-                if let Some(issue) = info.issues.as_ref() {
-                    writeln!(&mut output, "### Row {}", info.excel_row())?;
-                    writeln!(&mut output, "{}", issue)?;
-                }
-                if let Some(img) = info.img_name {
-                    let maybe_stem = img.file_stem().map(|s| s.to_string_lossy().into_owned());
-                    imgs.insert(img);
-                    if let Some(stem) = maybe_stem {
-                        *counts.entry(stem).or_insert(0) += 1;
-                    }
-                }
+    let mut expected_images = HashSet::new();
+    let mut name_count = HashMap::new();
+    for sheet in &sheets {
+        let headers = sheet.headers();
+        if headers.iter().all(|h| h.trim().is_empty()) {
+            continue;
+        }
+        for column in sheet.columns().renamed() {
+            log::info!("{}", column);
+        }
+        let layout = sd3::check_layout(&headers, Layout::MifcImage)
+            .map_err(Fail::compat)
+            .with_context(|| match sheet.name() {
+                Some(name) => format!("checking the header of sheet <{}>", name),
+                None => "checking the image metadata header".to_string(),
+            })?;
+        if !layout.unexpected.is_empty() {
+            log::info!("unexpected column(s): {}", layout.unexpected.join(", "));
+        }
 
-                Ok((imgs, counts))
-            },
-        )?;
+        let metadata_iter = sheet
+            .records::<MifcImage>()
+            .map_err(Fail::compat)
+            .context("reading image metadata header")?;
+
+        for record in metadata_iter {
+            let info = match record {
+                Ok(Located { location, record }) => summarize_row(location, &record),
+                Err(e) => match e.location().cloned() {
+                    Some(location) => RowInfo::new(
+                        location,
+                        None,
+                        format!("* issue parsing row in metadata file: {}", e),
+                    ),
+                    None => return Err(e.compat()).context("reading image metadata"),
+                },
+            };
+
+            if let Some(issue) = info.issues.as_ref() {
+                writeln!(&mut output, "### {}", info.heading(with_sheet))?;
+                writeln!(&mut output, "{}", issue)?;
+            }
+            if let Some(img) = info.img_name {
+                let maybe_stem = img.file_stem().map(|s| s.to_string_lossy().into_owned());
+                expected_images.insert(img);
+                if let Some(stem) = maybe_stem {
+                    *name_count.entry(stem).or_insert(0) += 1;
+                }
+            }
+        }
+    }
 
     log::info!("{:?}", &expected_images);
 
@@ -72,27 +96,32 @@ pub fn qc_images<W: Write>(
 }
 
 struct RowInfo {
-    // 0-index data row number (add two for excel row number)
-    number: usize,
+    // where the row was read from
+    location: Location,
     img_name: Option<PathBuf>,
     issues: Option<String>,
 }
 
 impl RowInfo {
-    fn new<'a, N, I>(number: usize, img: N, iss: I) -> Self
+    fn new<'a, N, I>(location: Location, img: N, iss: I) -> Self
     where
         N: Into<Option<PathBuf>>,
         I: Into<Option<String>>,
     {
         Self {
-            number,
+            location,
             img_name: img.into(),
             issues: iss.into(),
         }
     }
 
-    fn excel_row(&self) -> usize {
-        self.number + 2
+    fn heading(&self, with_sheet: bool) -> String {
+        match self.location.sheet.as_ref() {
+            Some(sheet) if with_sheet => {
+                format!("Row {} of sheet \"{}\"", self.location.row, sheet)
+            }
+            _ => format!("Row {}", self.location.row),
+        }
     }
 }
 
@@ -109,16 +138,17 @@ fn write_output_prologue<W: Write>(mut wtr: W, file: &str) -> io::Result<()> {
 fn row_summarizer<'m>(
     allowed: &'m VocabMaps,
     imgdir: &'m Path,
-) -> impl Fn(usize, &MifcImage) -> RowInfo + 'm {
+) -> impl Fn(Location, &MifcImage) -> RowInfo + 'm {
     use std::iter::once;
 
-    let check_target = make_checker("Target/Analyte", &allowed.targets);
-    let check_method = make_checker("Method/Kit", &allowed.methods);
-    let check_unit = make_checker("Value Unit", &allowed.units);
-    let check_location = make_checker("Sample Location", &allowed.locations);
-    let check_chip = make_checker("Chip ID", &allowed.chips);
+    let check_target = make_checker(MifcField::Target, &allowed.targets);
+    let check_method = make_checker(MifcField::Method, &allowed.methods);
+    let check_unit = make_checker(MifcField::ValueUnit, &allowed.units);
+    let check_location = make_checker(MifcField::SampleLocation, &allowed.locations);
+    let check_chip = make_checker(MifcField::ChipId, &allowed.chips);
 
-    move |i, row| {
+    move |location, row| {
+        let i = location.row;
         let img = {
             let mut i = imgdir.to_path_buf();
             i.push(&row.file);
@@ -131,6 +161,11 @@ fn row_summarizer<'m>(
             .chain(once(check_location(i, row)))
             .chain(once(check_chip(i, row)))
             .chain(once(check_image(i, &img)))
+            .chain(
+                row.validate()
+                    .into_iter()
+                    .map(|issue| Some(format!("* row {} {}", i, issue))),
+            )
             .filter_map(|x| x)
             .fold(None, |s: Option<String>, iss| {
                 s.map(|mut s| {
@@ -141,31 +176,28 @@ fn row_summarizer<'m>(
                 .or_else(|| Some(iss.to_string()))
             });
 
-        RowInfo::new(i, img, issues)
+        RowInfo::new(location, img, issues)
     }
 }
 
-/// A factory to create functions that check for metadata fields in the MIFC file
+/// A factory to create functions that check for metadata fields in the MIFC file.
+/// The functions take the row's spreadsheet row number.
 fn make_checker<'m>(
-    col_name: &'m str,
+    field: MifcField,
     allowed_vocab: &'m VocabSet,
 ) -> impl Fn(usize, &MifcImage) -> Option<String> + 'm {
     move |i, row| {
-        let lowercase;
-        let raw = row.get_vocab_field(col_name);
+        let raw = row.get_field(field).unwrap_or_default();
         let value = if allowed_vocab.case_sensitive {
             raw
         } else {
-            lowercase = raw.to_lowercase();
-            &lowercase
+            raw.to_lowercase().into()
         };
 
-        if !allowed_vocab.values.contains(value) {
+        if !allowed_vocab.values.contains(value.as_ref()) {
             Some(format!(
                 r#"* row {} field "{}" is not in MPS: "{}""#,
-                i + 2,
-                col_name,
-                value
+                i, field, value
             ))
         } else {
             None
@@ -185,12 +217,12 @@ fn check_image(i: usize, img: &Path) -> Option<String> {
     let file_err = |i| format!("* row {} image path is not a file: '{}'", i, &img.display());
 
     std::fs::metadata(&img)
-        .map_err(|e| find_err(i + 2, e))
+        .map_err(|e| find_err(i, e))
         .and_then(|f| {
             if f.is_file() {
                 Ok(())
             } else {
-                Err(file_err(i + 2))
+                Err(file_err(i))
             }
         })
         .err()
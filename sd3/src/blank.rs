@@ -0,0 +1,243 @@
+//! Background subtraction of blank (e.g. fresh media) rows. Blanks are recognized
+//! by their Chip ID or Sample Location, and each other row has the mean of the
+//! blanks with the same method, target, time, and plate subtracted from its Value.
+use std::collections::HashMap;
+use failure::{Fail};
+use units::{SIError, Uncertain, Unit, self};
+use crate::mifc::{Mifc, MifcNorm};
+use crate::lookup::key;
+
+#[derive(Debug, Fail)]
+pub enum BlankError {
+    #[fail(display = "no blank had the row's method, target, time, and plate")]
+    NoBlank,
+    #[fail(display = "couldn't convert the row's blank into its Value Unit")]
+    Unit(#[cause] SIError),
+}
+
+/// Which rows are blanks: rows with one of the Chip IDs or Sample Locations, ignoring
+/// case. A Chip ID also matches IDs that start with it and a separator, so "blank"
+/// matches "Blank_P4_A1" and "blank 2", but not "blanket".
+#[derive(Debug, Clone, Default)]
+pub struct BlankRules {
+    chip_ids: Vec<String>,
+    locations: Vec<String>,
+}
+
+impl BlankRules {
+    pub fn new() -> Self {
+        BlankRules::default()
+    }
+
+    pub fn add_chip_id(&mut self, id: &str) {
+        self.chip_ids.push(key(id));
+    }
+
+    pub fn add_location(&mut self, location: &str) {
+        self.locations.push(key(location));
+    }
+
+    /// Check if there aren't any rules, so that no row is a blank
+    pub fn is_empty(&self) -> bool {
+        self.chip_ids.is_empty() && self.locations.is_empty()
+    }
+
+    pub fn is_blank(&self, row: &Mifc) -> bool {
+        let id = key(&row.id);
+        let is_id = |blank: &String| id.strip_prefix(blank.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(|c: char| c == '_' || c == '-' || c.is_whitespace()));
+
+        self.chip_ids.iter().any(is_id) || self.locations.contains(&key(&row.sample_loc))
+    }
+}
+
+/// What a blank and a row have to share to be matched
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct BlankKey {
+    method: String,
+    target: String,
+    minutes: i64,
+    plate: Option<String>,
+}
+
+impl BlankKey {
+    fn new(row: &Mifc) -> Self {
+        BlankKey {
+            method: key(&row.method),
+            target: key(&row.target),
+            minutes: row.time.total_minutes().round() as i64,
+            plate: row.assay_plate_id.as_deref().map(key).filter(|p| !p.is_empty()),
+        }
+    }
+}
+
+/// The Values of the blank rows in a set of data, grouped by method, target, time, and plate
+#[derive(Debug, Default)]
+pub struct Blanks {
+    values: HashMap<BlankKey, Vec<(Uncertain, Unit)>>,
+}
+
+impl Blanks {
+    pub fn new() -> Self {
+        Blanks::default()
+    }
+
+    /// Add a blank row, returning if it was added. Excluded rows, and rows without
+    /// a Value or a known Value Unit, can't be used as blanks.
+    pub fn add(&mut self, row: &Mifc) -> bool {
+        if row.exclude { return false }
+        let unit = match row.value_unit.as_ref().map(|u| u.parse::<Unit>()) {
+            Some(Ok(u)) => u,
+            _ => return false,
+        };
+        let value = match row.value {
+            Some(v) => Uncertain::new(v, row.uncertainty),
+            None => return false,
+        };
+
+        self.values.entry(BlankKey::new(row)).or_default().push((value, unit));
+        true
+    }
+
+    /// The number of blank rows
+    pub fn len(&self) -> usize {
+        self.values.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The mean of the blanks that match `row`, in `unit`, and the number of blanks
+    fn mean(&self, row: &Mifc, unit: Unit) -> Result<(Uncertain, usize), BlankError> {
+        let blanks = self.values.get(&BlankKey::new(row)).ok_or(BlankError::NoBlank)?;
+        let n = blanks.len() as f64;
+
+        let mut sum = 0.0;
+        let mut variance = None;
+        for &(value, from) in blanks.iter() {
+            let value = units::convert((value, from), unit).map_err(BlankError::Unit)?;
+            sum += value.value;
+            if let Some(u) = value.uncertainty {
+                variance = Some(variance.unwrap_or(0.0) + u * u);
+            }
+        }
+        let mean = Uncertain::new(sum / n, variance.map(|v: f64| v.sqrt() / n));
+
+        Ok((mean, blanks.len()))
+    }
+}
+
+impl Mifc {
+    /// Subtract the mean of the matching blanks from this row's Value, and note it in
+    /// the Notes. Returns the Value that was subtracted, in this row's Value Unit.
+    /// Excluded rows, and rows without a Value or Value Unit, are left unchanged.
+    pub fn subtract_blank(&mut self, blanks: &Blanks) -> Result<Option<f64>, BlankError> {
+        if self.exclude { return Ok(None) }
+        let (value, unit) = match (self.value, self.value_unit.as_ref()) {
+            (Some(v), Some(u)) => (Uncertain::new(v, self.uncertainty), u.parse::<Unit>().map_err(BlankError::Unit)?),
+            _ => return Ok(None),
+        };
+
+        let (blank, n) = blanks.mean(self, unit)?;
+        let subtracted = value - blank;
+        self.value = Some(subtracted.value);
+        self.uncertainty = subtracted.uncertainty;
+
+        let mut note = format!("Subtracted a blank of {:.4} {}", blank.value, unit);
+        if n > 1 {
+            note.push_str(&format!(" (the mean of {} blanks)", n));
+        }
+        self.notes = match self.notes.take() {
+            Some(mut n) if !n.is_empty() => { n.push_str(" || "); n.push_str(&note); Some(n) },
+            _ => Some(note),
+        };
+
+        Ok(Some(blank.value))
+    }
+}
+
+impl MifcNorm {
+    /// Check if this row is a blank by `rules`
+    pub fn is_blank(&self, rules: &BlankRules) -> bool {
+        rules.is_blank(&self.mifc)
+    }
+
+    /// Add this row to `blanks`, returning if it was added
+    pub fn add_to(&self, blanks: &mut Blanks) -> bool {
+        blanks.add(&self.mifc)
+    }
+
+    /// Subtract the mean of the matching blanks from this row's Value, before it's normalized
+    pub fn subtract_blank(&mut self, blanks: &Blanks) -> Result<Option<f64>, BlankError> {
+        self.mifc.subtract_blank(blanks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::MifcTime;
+    use test_utils::double_comparable;
+
+    fn row(id: &str, day: f64, value: f64, unit: &str) -> Mifc {
+        Mifc {
+            id: id.to_string(),
+            assay_plate_id: Some("P1".to_string()),
+            time: MifcTime::from_dhm(day, 0.0, 0.0),
+            value: Some(value),
+            value_unit: Some(unit.to_string()),
+            ..Mifc::test_row()
+        }
+    }
+
+    #[test]
+    fn blank_rules() {
+        let mut rules = BlankRules::new();
+        assert!(!rules.is_blank(&row("blank", 1.0, 1.0, "ng/mL")));
+
+        rules.add_chip_id("Blank");
+        rules.add_location("Media");
+        for id in ["blank", " BLANK ", "Blank_P4_A1", "blank 2", "blank-3"].iter() {
+            assert!(rules.is_blank(&row(id, 1.0, 1.0, "ng/mL")), "{} should be a blank", id);
+        }
+        assert!(!rules.is_blank(&row("blanket", 1.0, 1.0, "ng/mL")));
+        assert!(!rules.is_blank(&row("C1", 1.0, 1.0, "ng/mL")));
+
+        let mut media = row("C1", 1.0, 1.0, "ng/mL");
+        media.sample_loc = "media".to_string();
+        assert!(rules.is_blank(&media));
+    }
+
+    #[test]
+    fn subtract_matching_blanks() {
+        const TOL: f64 = 1e-9;
+        let mut blanks = Blanks::new();
+        assert!(blanks.add(&row("blank", 1.0, 1.0, "ng/mL")));
+        assert!(blanks.add(&row("blank", 1.0, 3000.0, "pg/mL")));
+        assert!(blanks.add(&row("blank", 2.0, 5.0, "ng/mL")));
+        let mut excluded = row("blank", 2.0, 100.0, "ng/mL");
+        excluded.exclude = true;
+        assert!(!blanks.add(&excluded));
+        assert_eq!(blanks.len(), 3);
+
+        // the mean of 1 ng/mL and 3 ng/mL
+        let mut chip = row("C1", 1.0, 12.0, "ng/mL");
+        assert!(double_comparable(chip.subtract_blank(&blanks).unwrap().unwrap(), 2.0, TOL));
+        assert!(double_comparable(chip.value.unwrap(), 10.0, TOL));
+        assert_eq!(chip.notes.as_deref(), Some("Subtracted a blank of 2.0000 ng/mL (the mean of 2 blanks)"));
+
+        let mut chip = row("C1", 2.0, 0.012, "µg/mL");
+        chip.notes = Some("diluted".to_string());
+        chip.subtract_blank(&blanks).unwrap();
+        assert!(double_comparable(chip.value.unwrap(), 0.007, TOL));
+        assert_eq!(chip.notes.as_deref(), Some("diluted || Subtracted a blank of 0.0050 µg/mL"));
+
+        let mut other_plate = row("C1", 1.0, 12.0, "ng/mL");
+        other_plate.assay_plate_id = Some("P2".to_string());
+        match other_plate.subtract_blank(&blanks) {
+            Err(BlankError::NoBlank) => assert_eq!(other_plate.value, Some(12.0)),
+            other => panic!("expected no blank for another plate, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Cell counts measured at a few times for each chip, like day 0, 7, and 14, which
+//! are interpolated into an Estimated Cell Number at the time of each sample
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use serde_derive::Deserialize;
+use failure::{Fail};
+use crate::header::ColumnAliases;
+use crate::io::Location;
+use crate::layout::Layout;
+use crate::lookup::{self, TableError, key};
+use crate::mifc::MifcNorm;
+use crate::time::MifcTime;
+
+#[derive(Debug, Fail)]
+pub enum CellCountError {
+    #[fail(display = "couldn't read cell counts")]
+    Table(#[cause] TableError),
+    #[fail(display = "{} has a negative Estimated Cell Number ({})", _0, _1)]
+    Negative(Location, f64),
+    #[fail(display = "no cell counts for chip <{}>", _0)]
+    NoCounts(String),
+    #[fail(display = "chip <{}> has a count of 0 cells between {} and {}, which can't be interpolated log-linearly", _0, _1, _2)]
+    ZeroCount(String, MifcTime, MifcTime),
+}
+
+impl From<TableError> for CellCountError {
+    fn from(e: TableError) -> Self {
+        CellCountError::Table(e)
+    }
+}
+
+/// How a cell count is estimated between two measured counts
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Interpolation {
+    /// A straight line between the counts
+    #[default]
+    Linear,
+    /// A straight line between the logs of the counts, i.e. exponential growth
+    LogLinear,
+    /// The last count before the sample (last observation carried forward)
+    Carry,
+}
+
+impl Interpolation {
+    pub fn all() -> impl Iterator<Item = Interpolation> {
+        use self::Interpolation::*;
+        [Linear, LogLinear, Carry].iter().cloned()
+    }
+
+    /// The spelling used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Interpolation::Linear => "linear",
+            Interpolation::LogLinear => "log-linear",
+            Interpolation::Carry => "locf",
+        }
+    }
+}
+
+impl fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        Interpolation::all()
+            .find(|i| i.name() == s)
+            .ok_or_else(|| format!("unknown interpolation <{}>; expected linear, log-linear, or locf", s))
+    }
+}
+
+/// The count of one row of a cell count file, after its Chip ID and time
+#[derive(Debug, Deserialize)]
+struct Count {
+    #[serde(rename = "Estimated Cell Number")]
+    count: f64,
+}
+
+/// A series of cell counts for each chip. Chip IDs are matched ignoring case and
+/// surrounding whitespace. Samples before the first count or after the last count of
+/// their chip get that count, since the counts aren't extrapolated.
+#[derive(Debug, Default)]
+pub struct CellCounts {
+    /// Each chip's counts, sorted by time
+    chips: HashMap<String, Vec<(MifcTime, f64)>>,
+    method: Interpolation,
+}
+
+impl CellCounts {
+    pub fn new() -> Self {
+        CellCounts::default()
+    }
+
+    /// Load the counts from every cell count sheet of a CSV or workbook file, which
+    /// has `Chip ID`, `Day`, and `Estimated Cell Number` columns, and optional `Hour`
+    /// and `Minute` columns
+    pub fn from_path<P: AsRef<Path>>(path: P, aliases: &ColumnAliases) -> Result<Self, CellCountError> {
+        let mut counts = CellCounts::new();
+        lookup::read_chip_times(path.as_ref(), aliases, Layout::CellCounts, |location, chip, time, Count { count }| {
+            if count < 0.0 {
+                return Err(CellCountError::Negative(location.clone(), count));
+            }
+            Ok(counts.insert(chip, time, count))
+        })?;
+
+        Ok(counts)
+    }
+
+    /// Estimate counts between measurements with `method`, which is linear by default
+    pub fn with_method(self, method: Interpolation) -> Self {
+        CellCounts { method, ..self }
+    }
+
+    /// Add the count of a chip at a time, returning `false` if the chip already had a count at that time
+    pub fn insert(&mut self, chip: &str, time: MifcTime, count: f64) -> bool {
+        let series = self.chips.entry(key(chip)).or_default();
+        match series.binary_search_by(|(t, _)| t.cmp(&time)) {
+            Ok(_) => false,
+            Err(i) => { series.insert(i, (time, count)); true },
+        }
+    }
+
+    /// The number of counts, over every chip
+    pub fn len(&self) -> usize {
+        self.chips.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chips.is_empty()
+    }
+
+    pub fn method(&self) -> Interpolation {
+        self.method
+    }
+
+    /// Estimate the cell count of `chip` at `time`
+    pub fn estimate(&self, chip: &str, time: MifcTime) -> Result<f64, CellCountError> {
+        let series = self.chips.get(&key(chip))
+            .ok_or_else(|| CellCountError::NoCounts(chip.to_string()))?;
+        let (before, after) = match series.iter().position(|(t, _)| *t > time) {
+            Some(0) => return Ok(series[0].1),
+            Some(i) => (series[i - 1], series[i]),
+            None => return Ok(series[series.len() - 1].1),
+        };
+        let ((t0, c0), (t1, c1)) = (before, after);
+        if t0 == time { return Ok(c0) }
+
+        let frac = (time.total_minutes() - t0.total_minutes()) / (t1.total_minutes() - t0.total_minutes());
+        match self.method {
+            Interpolation::Linear => Ok(c0 + frac * (c1 - c0)),
+            Interpolation::LogLinear if c0 == 0.0 || c1 == 0.0 => Err(CellCountError::ZeroCount(chip.to_string(), t0, t1)),
+            Interpolation::LogLinear => Ok((c0.ln() + frac * (c1.ln() - c0.ln())).exp()),
+            Interpolation::Carry => Ok(c0),
+        }
+    }
+}
+
+impl MifcNorm {
+    /// Replace this row's Estimated Cell Number, or fill in a blank one, with one interpolated
+    /// from `counts` at the row's time, returning the new estimate, or `None` if the row doesn't
+    /// have normalization info. The estimate doesn't keep the row's cell number uncertainty.
+    pub fn interpolate_cells(&mut self, counts: &CellCounts) -> Result<Option<f64>, CellCountError> {
+        let info = match self.normal_info.as_mut() {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+        let estimate = counts.estimate(&self.mifc.id, self.mifc.time)?;
+        info.cell_count = Some(estimate);
+        info.cell_count_sd = None;
+
+        Ok(Some(estimate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::mifc::MifcNormError;
+    use crate::norm::NormStrategy;
+    use test_utils::double_comparable;
+
+    fn day(d: f64) -> MifcTime {
+        MifcTime::from_dhm(d, 0.0, 0.0)
+    }
+
+    fn series(method: Interpolation) -> CellCounts {
+        let mut counts = CellCounts::new().with_method(method);
+        assert!(counts.insert("C1", day(7.0), 400_000.0));
+        assert!(counts.insert("C1", day(0.0), 100_000.0));
+        assert!(counts.insert("c1", day(14.0), 200_000.0));
+        assert!(!counts.insert(" C1 ", day(7.0), 1.0));
+        counts
+    }
+
+    #[test]
+    fn interpolate_counts() {
+        const TOL: f64 = 1e-6;
+        let cases = [
+            (Interpolation::Linear, 3.5, 250_000.0),
+            (Interpolation::Linear, 10.5, 300_000.0),
+            (Interpolation::LogLinear, 3.5, 200_000.0),
+            (Interpolation::LogLinear, 10.5, 200_000.0 * 2f64.sqrt()),
+            (Interpolation::Carry, 3.5, 100_000.0),
+            (Interpolation::Carry, 13.9, 400_000.0),
+        ];
+        for &(method, d, expected) in cases.iter() {
+            let estimate = series(method).estimate("C1", day(d)).unwrap();
+            assert!(double_comparable(estimate, expected, TOL), "{} at day {}: {}", method, d, estimate);
+        }
+
+        for method in Interpolation::all() {
+            assert_eq!(method.name().parse::<Interpolation>(), Ok(method));
+            let counts = series(method);
+            assert_eq!(counts.estimate("C1", day(7.0)).unwrap(), 400_000.0);
+            // no extrapolation
+            assert_eq!(counts.estimate("C1", MifcTime::from_dhm(-1.0, 0.0, 0.0)).unwrap(), 100_000.0);
+            assert_eq!(counts.estimate("C1", day(20.0)).unwrap(), 200_000.0);
+            assert!(matches!(counts.estimate("C2", day(1.0)), Err(CellCountError::NoCounts(_))));
+        }
+
+        let mut counts = CellCounts::new().with_method(Interpolation::LogLinear);
+        counts.insert("C1", day(0.0), 0.0);
+        counts.insert("C1", day(2.0), 10.0);
+        assert_eq!(counts.estimate("C1", day(0.0)).unwrap(), 0.0);
+        assert!(matches!(counts.estimate("C1", day(1.0)), Err(CellCountError::ZeroCount(..))));
+    }
+
+    #[test]
+    fn interpolate_row_cell_number() {
+        let path = std::env::temp_dir().join(format!("sd3-cells-{}.csv", std::process::id()));
+        fs::write(&path, "Chip ID,Day,Hour,Estimated Cell Number\nC1,0,,100000\nC1,2,,300000\nC2,1,12,5000\n").unwrap();
+        let counts = CellCounts::from_path(&path, &ColumnAliases::new()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(counts.len(), 3);
+
+        let input = "Chip ID,Method/Kit,Target/Analyte,Sample Location,Day,Hour,Minute,Value,Value Unit,\
+            Duration Sample Collection (days),Duration Sample Collection (hours),Duration Sample Collection (minutes),\
+            Sample Volume,Sample Volume Unit,Estimated Cell Number,Estimated Cell Number Uncertainty\n\
+            C1,ELISA,Albumin,effluent,1,0,0,12.5,ng/mL,1,0,0,100,µL,80000,5000\n";
+        let mut row: MifcNorm = csv::Reader::from_reader(input.as_bytes()).deserialize().next().unwrap().unwrap();
+
+        assert_eq!(row.interpolate_cells(&counts).unwrap(), Some(200_000.0));
+        let info = row.normal_info.as_ref().unwrap();
+        assert_eq!(info.cell_count, Some(200_000.0));
+        assert_eq!(info.cell_count_sd, None);
+    }
+
+    #[test]
+    fn interpolate_blank_cell_number() {
+        let mut counts = CellCounts::new();
+        counts.insert("C1", day(0.0), 100_000.0);
+        counts.insert("C1", day(2.0), 300_000.0);
+
+        // without an Estimated Cell Number column, or with a blank one
+        let inputs = [
+            "Chip ID,Method/Kit,Target/Analyte,Sample Location,Day,Hour,Minute,Value,Value Unit,\
+                Duration Sample Collection (days),Duration Sample Collection (hours),Duration Sample Collection (minutes),\
+                Sample Volume,Sample Volume Unit\n\
+                C1,ELISA,Albumin,effluent,1,0,0,12.5,ng/mL,1,0,0,100,µL\n",
+            "Chip ID,Method/Kit,Target/Analyte,Sample Location,Day,Hour,Minute,Value,Value Unit,\
+                Duration Sample Collection (days),Duration Sample Collection (hours),Duration Sample Collection (minutes),\
+                Sample Volume,Sample Volume Unit,Estimated Cell Number\n\
+                C1,ELISA,Albumin,effluent,1,0,0,12.5,ng/mL,1,0,0,100,µL,\n",
+        ];
+        let strategy = NormStrategy::default();
+        for input in inputs.iter() {
+            let read = || -> MifcNorm {
+                csv::Reader::from_reader(input.as_bytes()).deserialize().next().unwrap().unwrap()
+            };
+            assert_eq!(read().normal_info.unwrap().cell_count, None);
+            assert!(matches!(read().into_normalized(&strategy), Err(MifcNormError::NoCellCount)));
+
+            let mut row = read();
+            assert_eq!(row.interpolate_cells(&counts).unwrap(), Some(200_000.0));
+            let normal = row.into_normalized(&strategy).unwrap();
+            // 12.5 ng/mL * 0.1 mL / 1 day / 0.2 million cells
+            assert!(double_comparable(normal.value.unwrap(), 6.25, 1e-9), "{:?}", normal.value);
+        }
+    }
+}
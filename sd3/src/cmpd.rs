@@ -2,6 +2,11 @@ use serde_derive::{Deserialize, Serialize};
 use failure::{Fail};
 use units::{SIUnit};
 use crate::mifc::Mifc;
+use crate::time::MifcTime;
+use crate::flag::{CautionFlag, ExclusionRules};
+use crate::de::blank_as_none;
+use crate::header::{ExtraColumns, KeepExtra};
+use crate::layout::Layout;
 use std::fmt::{self, Write};
 
 #[derive(Debug, Fail)]
@@ -30,28 +35,43 @@ pub struct CmpdDit {
     method: String,
     #[serde(rename = "Target/Analyte")]
     target: String,
-    #[serde(rename = "Result")]
+    #[serde(rename = "Result", default, deserialize_with = "blank_as_none")]
     value: Option<f64>,
-    #[serde(rename = "Result Unit")] // TODO: SIUnit?
+    #[serde(rename = "Result Unit", default, deserialize_with = "blank_as_none")]
     value_unit: Option<String>,
-    #[serde(rename = "Dilution")]
+    #[serde(rename = "Dilution", default, deserialize_with = "blank_as_none")]
     dilution: Option<f64>,
     #[serde(rename = "Location")]
     location: String,
-    #[serde(rename = "Note (optional)")]
+    #[serde(rename = "Note (optional)", default, deserialize_with = "blank_as_none")]
     note: Option<String>,
-    #[serde(rename = "Flag (optional)")]
-    flag: Option<String>,
-    #[serde(rename = "Replicate (optional)")]
+    #[serde(rename = "Flag (optional)", default, deserialize_with = "blank_as_none")]
+    flag: Option<CautionFlag>,
+    #[serde(rename = "Replicate (optional)", default, deserialize_with = "blank_as_none")]
     replicate: Option<u32>,
-    #[serde(rename = "TCTCxRef (optional)")]
+    #[serde(rename = "TCTCxRef (optional)", default, deserialize_with = "blank_as_none")]
     xref: Option<String>,
-    #[serde(rename = "Cell Count")]
+    #[serde(rename = "Cell Count", default, deserialize_with = "blank_as_none")]
     cell_count: Option<f64>,
-    #[serde(rename = "Sample Duration")]
+    #[serde(rename = "Sample Duration", default, deserialize_with = "blank_as_none")]
     duration: Option<String>,
-    #[serde(rename = "Sample Volume (µL)")]
+    #[serde(rename = "Sample Volume (µL)", default, deserialize_with = "blank_as_none")]
     vol: Option<String>,
+    #[serde(rename = "Uncertainty (optional)", default, deserialize_with = "blank_as_none")]
+    uncertainty: Option<f64>,
+    /// Cells of other columns, which are passed through to the MIFC row
+    #[serde(skip)]
+    extra: ExtraColumns,
+}
+
+impl KeepExtra for CmpdDit {
+    fn layout() -> Layout {
+        Layout::CmpdDit
+    }
+
+    fn extra_mut(&mut self) -> &mut ExtraColumns {
+        &mut self.extra
+    }
 }
 
 impl CmpdDit {
@@ -62,22 +82,24 @@ impl CmpdDit {
     pub fn chip_id(&self) -> &String {
         &self.id
     }
-    pub fn into_mifc(self) -> Result<Mifc, CmpdDitError> {
+
+    /// Convert into a MIFC row, marking it as excluded if its flag has a code that `rules` excludes
+    pub fn into_mifc(self, rules: &ExclusionRules) -> Result<Mifc, CmpdDitError> {
         let time = self.time;
-        let (day, hour, min) = parse_time_str(&time)
-            .ok_or_else(|| CmpdDitError::TimeCvrt(time))?;
+        let time: MifcTime = time.parse()
+            .map_err(|_| CmpdDitError::TimeCvrt(time))?;
         
         let dilution = self.dilution.unwrap_or(1.0);
         let value = self.value.map(|v| v * dilution);
-        let exclude = self.flag.as_ref()
-            .filter(|s| s.contains("O") || s.contains("W") || s.contains("F") )
-            .map(|_| "X".to_string());
+        let uncertainty = self.uncertainty.map(|u| (u * dilution).abs());
+        let flag = self.flag;
+        let exclude = flag.as_ref().is_some_and(|f| rules.excludes(f));
         
         // try to standardize the value unit, if possible
         let value_unit = self.value_unit.map(|mut unit| {
             if let Some(standard) = SIUnit::standardize(&unit) {
                unit.clear();
-               unit.write_str(standard)?;
+               unit.write_str(&standard)?;
             }
 
             Ok(unit)
@@ -92,25 +114,17 @@ impl CmpdDit {
             target: self.target,
             subtarget: None,
             sample_loc: self.location,
-            day,
-            hour,
-            min,
+            time,
             value,
             value_unit,
-            flag: self.flag,
+            flag,
             exclude,
+            exclude_text: None,
             notes: self.note,
             replicate: self.replicate.map(|i| i as f32),
             xref: self.xref,
+            uncertainty,
+            extra: self.extra,
         })
     }
 }
-
-fn parse_time_str(time: &str) -> Option<(f64, f64, f64)> {
-    let mut parts = time.split(".");
-    let day  = parts.next()?.parse().ok()?;
-    let hour = parts.next().map(str::parse).unwrap_or(Ok(0.0)).ok()?; 
-    let min  = parts.next().map(str::parse).unwrap_or(Ok(0.0)).ok()?;
-
-    Some((day, hour, min)) 
-}
@@ -0,0 +1,233 @@
+//! Deserialization helpers shared by the SD3 record types
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use serde::de::{self, Deserializer, Visitor};
+
+/// Deserialize an optional field, treating a missing, empty, or whitespace-only cell
+/// as `None`. The cell can be text or a number (as Excel cells often are) and is
+/// parsed into `T` from its trimmed text, so that a numeric cell can fill a text field.
+/// Use with `#[serde(default, deserialize_with = "blank_as_none")]`.
+pub(crate) fn blank_as_none<'de, D, T>(d: D) -> Result<Option<T>, D::Error>
+where D: Deserializer<'de>,
+      T: FromStr,
+      T::Err: fmt::Display,
+{
+    d.deserialize_option(BlankVisitor(PhantomData))
+}
+
+struct BlankVisitor<T>(PhantomData<T>);
+
+impl<T> BlankVisitor<T>
+where T: FromStr,
+      T::Err: fmt::Display,
+{
+    fn parse<E: de::Error>(&self, cell: &str) -> Result<Option<T>, E> {
+        match cell.trim() {
+            "" => Ok(None),
+            text => text.parse()
+                .map(Some)
+                .map_err(|e| E::custom(format!("couldn't read <{}>: {}", text, e))),
+        }
+    }
+}
+
+impl<'de, T> Visitor<'de> for BlankVisitor<T>
+where T: FromStr,
+      T::Err: fmt::Display,
+{
+    type Value = Option<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an empty cell, or a text or number cell")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D>(self, d: D) -> Result<Self::Value, D::Error>
+    where D: Deserializer<'de>
+    {
+        d.deserialize_any(self)
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+        self.parse(s)
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Self::Value, E> {
+        self.parse(&b.to_string())
+    }
+
+    fn visit_i64<E: de::Error>(self, i: i64) -> Result<Self::Value, E> {
+        self.parse(&i.to_string())
+    }
+
+    fn visit_u64<E: de::Error>(self, u: u64) -> Result<Self::Value, E> {
+        self.parse(&u.to_string())
+    }
+
+    fn visit_f64<E: de::Error>(self, f: f64) -> Result<Self::Value, E> {
+        self.parse(&f.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use calamine::{DataType, Range, RangeDeserializerBuilder};
+    use crate::{CmpdDit, ExclusionRules, Mifc, MifcImage, MifcNorm, NormStrategy};
+
+    const MIFC_HEADER: [&str; 18] = [
+        "Chip ID", "Assay Plate ID", "Assay Well ID", "Method/Kit", "Target/Analyte", "Subtarget",
+        "Sample Location", "Day", "Hour", "Minute", "Value", "Value Unit", "Caution Flag",
+        "Exclude", "Notes", "Replicate", "Cross Reference", "Value Uncertainty",
+    ];
+    const MIFC_ROW: [&str; 18] = [
+        "C1", "", " ", "ELISA", "Albumin", "  ", "effluent", "1", "0", "0", "12.5", "ng/mL", "",
+        "   ", "\t", "", "", " ",
+    ];
+    const NORM_HEADER: [&str; 9] = [
+        "Duration Sample Collection (days)", "Duration Sample Collection (hours)",
+        "Duration Sample Collection (minutes)", "Sample Volume", "Sample Volume Unit",
+        "Estimated Cell Number", "Molecular Weight (g/mol)", "Sample Volume Uncertainty",
+        "Estimated Cell Number Uncertainty",
+    ];
+    const NORM_ROW: [&str; 9] = ["1", "0", "0", "200", "µL", "20000", " ", "", "  "];
+    const IMAGE_HEADER: [&str; 11] = [
+        "Image File Name", "Image Field", "Image Field Description", "Image Magnification",
+        "Image Resolution", "Image Resolution Unit", "Image Sample Label",
+        "Image Sample Label Description", "Image Wavelength (nm)", "Image Color Mapping",
+        "Image Setting Note",
+    ];
+    const IMAGE_ROW: [&str; 11] = ["a.tif", "1", " ", "10", "0.5", "µm", "DAPI", "", "  ", "blue", "\t"];
+    const CMPD_HEADER: [&str; 17] = [
+        "Group Indicator", "Chip ID", "Time", "Method/Kit", "Target/Analyte", "Result", "Result Unit",
+        "Dilution", "Location", "Note (optional)", "Flag (optional)", "Replicate (optional)",
+        "TCTCxRef (optional)", "Cell Count", "Sample Duration", "Sample Volume (µL)", "Uncertainty (optional)",
+    ];
+    const CMPD_ROW: [&str; 17] = [
+        "Low", "C1", "2.0.0", "ELISA", "Albumin", "12.5", "ng/mL", " ", "effluent", "  ", " ", "",
+        "\t", " ", "", " ", " ",
+    ];
+
+    fn csv_text(header: &[&str], row: &[&str]) -> String {
+        format!("{}\n{}\n", header.join(","), row.join(","))
+    }
+
+    /// A sheet with a header row and one data row. Blank cells are left empty, except for
+    /// whitespace-only cells, which are entered as text, as they would be in Excel.
+    /// Numeric cells are entered as numbers.
+    fn sheet(header: &[&str], row: &[&str]) -> Range<DataType> {
+        let mut range = Range::new((0, 0), (1, header.len() as u32 - 1));
+        for (c, h) in header.iter().enumerate() {
+            range.set_value((0, c as u32), DataType::String(h.to_string()));
+        }
+        for (c, cell) in row.iter().enumerate() {
+            let value = match cell.parse::<f64>() {
+                _ if cell.is_empty() => continue,
+                Ok(f) => DataType::Float(f),
+                Err(_) => DataType::String(cell.to_string()),
+            };
+            range.set_value((1, c as u32), value);
+        }
+
+        range
+    }
+
+    fn from_csv<T: serde::de::DeserializeOwned>(header: &[&str], row: &[&str]) -> T {
+        let text = csv_text(header, row);
+        let mut rdr = csv::Reader::from_reader(text.as_bytes());
+        rdr.deserialize().next().unwrap().unwrap()
+    }
+
+    fn from_sheet<T: serde::de::DeserializeOwned>(header: &[&str], row: &[&str]) -> T {
+        let range = sheet(header, row);
+        RangeDeserializerBuilder::new()
+            .has_headers(true)
+            .from_range(&range)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+    }
+
+    fn assert_blank_mifc(mifc: &Mifc) {
+        assert_eq!(mifc.assay_plate_id, None);
+        assert_eq!(mifc.assay_well_id, None);
+        assert_eq!(mifc.subtarget, None);
+        assert_eq!(mifc.flag, None);
+        assert!(!mifc.exclude);
+        assert_eq!(mifc.notes, None);
+        assert_eq!(mifc.replicate, None);
+        assert_eq!(mifc.xref, None);
+        assert_eq!(mifc.uncertainty, None);
+        assert_eq!(mifc.value, Some(12.5));
+    }
+
+    #[test]
+    fn blank_mifc_cells() {
+        assert_blank_mifc(&from_csv(&MIFC_HEADER, &MIFC_ROW));
+        assert_blank_mifc(&from_sheet(&MIFC_HEADER, &MIFC_ROW));
+    }
+
+    #[test]
+    fn blank_mifc_image_cells() {
+        let header: Vec<&str> = MIFC_HEADER.iter().chain(IMAGE_HEADER.iter()).cloned().collect();
+        let row: Vec<&str> = MIFC_ROW.iter().chain(IMAGE_ROW.iter()).cloned().collect();
+
+        let images: [MifcImage; 2] = [from_csv(&header, &row), from_sheet(&header, &row)];
+        for image in images.iter() {
+            assert_blank_mifc(&image.mifc);
+            assert_eq!(image.field_desc, None);
+            assert_eq!(image.label_desc, None);
+            assert_eq!(image.wavelength, None);
+            assert_eq!(image.image_note, None);
+        }
+    }
+
+    #[test]
+    fn blank_mifc_norm_cells() {
+        let header: Vec<&str> = MIFC_HEADER.iter().chain(NORM_HEADER.iter()).cloned().collect();
+        let row: Vec<&str> = MIFC_ROW.iter().chain(NORM_ROW.iter()).cloned().collect();
+
+        let rows: [MifcNorm; 2] = [from_csv(&header, &row), from_sheet(&header, &row)];
+        for row in rows {
+            // a whitespace-only Exclude doesn't exclude the row, and blank
+            // uncertainties and molecular weight are left out
+            let normalized = row.into_normalized(&NormStrategy::default()).unwrap();
+            assert_eq!(normalized.uncertainty, None);
+            assert!(!normalized.exclude);
+        }
+    }
+
+    #[test]
+    fn blank_cmpd_cells() {
+        let rows: [CmpdDit; 2] = [from_csv(&CMPD_HEADER, &CMPD_ROW), from_sheet(&CMPD_HEADER, &CMPD_ROW)];
+        for row in rows {
+            let mifc = row.into_mifc(&ExclusionRules::default()).unwrap();
+            assert_eq!(mifc.value, Some(12.5));
+            assert_eq!(mifc.notes, None);
+            assert_eq!(mifc.flag, None);
+            assert!(!mifc.exclude);
+            assert_eq!(mifc.replicate, None);
+            assert_eq!(mifc.xref, None);
+            assert_eq!(mifc.uncertainty, None);
+        }
+    }
+
+    #[test]
+    fn numeric_cells_fill_text_fields() {
+        let mut row = MIFC_ROW;
+        row[1] = "42";
+        row[15] = "2";
+        let mifc: Mifc = from_sheet(&MIFC_HEADER, &row);
+
+        assert_eq!(mifc.assay_plate_id.as_deref(), Some("42"));
+        assert_eq!(mifc.replicate, Some(2.0));
+    }
+}
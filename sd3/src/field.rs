@@ -0,0 +1,251 @@
+//! The MIFC and MIFC-Image columns, and lookups of a row's value for any of them
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use failure::{Fail};
+use crate::mifc::{Mifc, MifcImage, MifcNorm};
+
+#[derive(Debug, Fail)]
+#[fail(display = "<{}> is not a MIFC or MIFC-Image column", _0)]
+pub struct UnkFieldError(String);
+
+/// A MIFC or MIFC-Image column
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum MifcField {
+    ChipId,
+    AssayPlateId,
+    AssayWellId,
+    Method,
+    Target,
+    Subtarget,
+    SampleLocation,
+    Day,
+    Hour,
+    Minute,
+    Value,
+    ValueUnit,
+    CautionFlag,
+    Exclude,
+    Notes,
+    Replicate,
+    CrossReference,
+    ValueUncertainty,
+    ImageFileName,
+    ImageField,
+    ImageFieldDescription,
+    ImageMagnification,
+    ImageResolution,
+    ImageResolutionUnit,
+    ImageSampleLabel,
+    ImageSampleLabelDescription,
+    ImageWavelength,
+    ImageColorMapping,
+    ImageSettingNote,
+}
+
+impl MifcField {
+    /// Every MIFC column, in the order they're written
+    pub const MIFC: [MifcField; 18] = {
+        use self::MifcField::*;
+        [
+            ChipId, AssayPlateId, AssayWellId, Method, Target, Subtarget, SampleLocation,
+            Day, Hour, Minute, Value, ValueUnit, CautionFlag, Exclude, Notes, Replicate,
+            CrossReference, ValueUncertainty,
+        ]
+    };
+    /// The columns that MIFC-Image adds after the MIFC columns
+    pub const IMAGE: [MifcField; 11] = {
+        use self::MifcField::*;
+        [
+            ImageFileName, ImageField, ImageFieldDescription, ImageMagnification, ImageResolution,
+            ImageResolutionUnit, ImageSampleLabel, ImageSampleLabelDescription, ImageWavelength,
+            ImageColorMapping, ImageSettingNote,
+        ]
+    };
+
+    /// Every MIFC and MIFC-Image column
+    pub fn all() -> impl Iterator<Item = MifcField> {
+        MifcField::MIFC.iter().chain(MifcField::IMAGE.iter()).cloned()
+    }
+
+    /// The column header
+    pub fn header(&self) -> &'static str {
+        use self::MifcField::*;
+
+        match self {
+            ChipId => "Chip ID",
+            AssayPlateId => "Assay Plate ID",
+            AssayWellId => "Assay Well ID",
+            Method => "Method/Kit",
+            Target => "Target/Analyte",
+            Subtarget => "Subtarget",
+            SampleLocation => "Sample Location",
+            Day => "Day",
+            Hour => "Hour",
+            Minute => "Minute",
+            Value => "Value",
+            ValueUnit => "Value Unit",
+            CautionFlag => "Caution Flag",
+            Exclude => "Exclude",
+            Notes => "Notes",
+            Replicate => "Replicate",
+            CrossReference => "Cross Reference",
+            ValueUncertainty => "Value Uncertainty",
+            ImageFileName => "Image File Name",
+            ImageField => "Image Field",
+            ImageFieldDescription => "Image Field Description",
+            ImageMagnification => "Image Magnification",
+            ImageResolution => "Image Resolution",
+            ImageResolutionUnit => "Image Resolution Unit",
+            ImageSampleLabel => "Image Sample Label",
+            ImageSampleLabelDescription => "Image Sample Label Description",
+            ImageWavelength => "Image Wavelength (nm)",
+            ImageColorMapping => "Image Color Mapping",
+            ImageSettingNote => "Image Setting Note",
+        }
+    }
+
+    /// Check if every row must have this column, rather than it being optional
+    pub fn is_required(&self) -> bool {
+        use self::MifcField::*;
+
+        matches!(self,
+            ChipId | Method | Target | SampleLocation | Day | Hour | Minute
+            | ImageFileName | ImageField | ImageMagnification | ImageResolution
+            | ImageResolutionUnit | ImageSampleLabel | ImageColorMapping
+        )
+    }
+
+    /// Check if this is one of the columns that only MIFC-Image has
+    pub fn is_image(&self) -> bool {
+        MifcField::IMAGE.contains(self)
+    }
+}
+
+impl fmt::Display for MifcField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.header())
+    }
+}
+
+impl FromStr for MifcField {
+    type Err = UnkFieldError;
+
+    /// Parse a column header, ignoring surrounding whitespace
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let header = s.trim();
+        MifcField::all()
+            .find(|f| f.header() == header)
+            .ok_or_else(|| UnkFieldError(s.to_string()))
+    }
+}
+
+fn text(s: &str) -> Option<Cow<'_, str>> {
+    Some(Cow::Borrowed(s))
+}
+
+fn opt_text(s: &Option<String>) -> Option<Cow<'_, str>> {
+    s.as_deref().map(Cow::Borrowed)
+}
+
+fn shown<T: ToString>(v: T) -> Option<Cow<'static, str>> {
+    Some(Cow::Owned(v.to_string()))
+}
+
+impl Mifc {
+    /// The value of a MIFC column as it would be written, or `None` if the cell
+    /// would be blank. MIFC-Image columns are always `None`.
+    pub fn get_field(&self, field: MifcField) -> Option<Cow<'_, str>> {
+        use self::MifcField::*;
+
+        match field {
+            ChipId => text(&self.id),
+            AssayPlateId => opt_text(&self.assay_plate_id),
+            AssayWellId => opt_text(&self.assay_well_id),
+            Method => text(&self.method),
+            Target => text(&self.target),
+            Subtarget => opt_text(&self.subtarget),
+            SampleLocation => text(&self.sample_loc),
+            Day => shown(self.time.written_dhm().0),
+            Hour => shown(self.time.written_dhm().1),
+            Minute => shown(self.time.written_dhm().2),
+            Value => self.value.and_then(shown),
+            ValueUnit => opt_text(&self.value_unit),
+            CautionFlag => self.flag.as_ref().and_then(shown),
+            Exclude => self.exclude_cell().map(Cow::Borrowed),
+            Notes => opt_text(&self.notes),
+            Replicate => self.replicate.and_then(shown),
+            CrossReference => opt_text(&self.xref),
+            ValueUncertainty => self.uncertainty.and_then(shown),
+            _ => None,
+        }
+    }
+}
+
+impl MifcImage {
+    /// The value of a MIFC or MIFC-Image column as it would be written,
+    /// or `None` if the cell would be blank
+    pub fn get_field(&self, field: MifcField) -> Option<Cow<'_, str>> {
+        use self::MifcField::*;
+
+        match field {
+            ImageFileName => text(&self.file),
+            ImageField => shown(self.field),
+            ImageFieldDescription => opt_text(&self.field_desc),
+            ImageMagnification => shown(self.magnification),
+            ImageResolution => shown(self.resolution),
+            ImageResolutionUnit => text(&self.resolution_unit),
+            ImageSampleLabel => text(&self.label),
+            ImageSampleLabelDescription => opt_text(&self.label_desc),
+            ImageWavelength => opt_text(&self.wavelength),
+            ImageColorMapping => text(&self.colormap),
+            ImageSettingNote => opt_text(&self.image_note),
+            mifc => self.mifc.get_field(mifc),
+        }
+    }
+}
+
+impl MifcNorm {
+    /// The value of a MIFC column as it would be written, or `None` if the cell
+    /// would be blank. MIFC-Image columns are always `None`.
+    pub fn get_field(&self, field: MifcField) -> Option<Cow<'_, str>> {
+        self.mifc.get_field(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_round_trip() {
+        for field in MifcField::all() {
+            assert_eq!(field.header().parse::<MifcField>().unwrap(), field);
+        }
+        assert_eq!(" Chip ID ".parse::<MifcField>().unwrap(), MifcField::ChipId);
+        assert!("Not a column".parse::<MifcField>().is_err());
+        assert!(MifcField::ImageWavelength.is_image());
+        assert!(!MifcField::Value.is_image());
+    }
+
+    #[test]
+    fn field_values() {
+        let text = "Chip ID,Assay Plate ID,Assay Well ID,Method/Kit,Target/Analyte,Subtarget,\
+            Sample Location,Day,Hour,Minute,Value,Value Unit,Caution Flag,Exclude,Notes,Replicate,\
+            Cross Reference,Image File Name,Image Field,Image Field Description,Image Magnification,\
+            Image Resolution,Image Resolution Unit,Image Sample Label,Image Sample Label Description,\
+            Image Wavelength (nm),Image Color Mapping,Image Setting Note\n\
+            C1,,,Imaging,Albumin,,tissue,1,12,0,,,W,X,,,,a.tif,2,,10,0.5,µm,DAPI,,405,blue,\n";
+        let mut rdr = csv::Reader::from_reader(text.as_bytes());
+        let image: MifcImage = rdr.deserialize().next().unwrap().unwrap();
+
+        assert_eq!(image.get_field(MifcField::ChipId).as_deref(), Some("C1"));
+        assert_eq!(image.get_field(MifcField::Hour).as_deref(), Some("12"));
+        assert_eq!(image.get_field(MifcField::CautionFlag).as_deref(), Some("W"));
+        assert_eq!(image.get_field(MifcField::Exclude).as_deref(), Some("X"));
+        assert_eq!(image.get_field(MifcField::ValueUnit), None);
+        assert_eq!(image.get_field(MifcField::ImageField).as_deref(), Some("2"));
+        assert_eq!(image.get_field(MifcField::ImageWavelength).as_deref(), Some("405"));
+        assert_eq!(image.mifc.get_field(MifcField::ImageFileName), None);
+    }
+}
@@ -0,0 +1,325 @@
+//! MPS Caution Flag codes, and the rules for which flagged rows get excluded.
+//! The rules default to excluding every known code, and can be loaded from a
+//! TOML or CSV file so that every tool excludes the same rows.
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use failure::{Fail};
+use log::{warn};
+use serde::ser::{Serialize, Serializer};
+use crate::mifc::Mifc;
+
+#[derive(Debug, Fail)]
+pub enum FlagError {
+    #[fail(display = "unknown Caution Flag code <{}>; expected one of O, W, or F", _0)]
+    UnkCode(String),
+    #[fail(display = "couldn't read flag rule file <{}>", _0)]
+    Io(String, #[cause] std::io::Error),
+    #[fail(display = "couldn't parse TOML flag rule file <{}>", _0)]
+    Toml(String, #[cause] toml::de::Error),
+    #[fail(display = "couldn't parse CSV flag rule file <{}>", _0)]
+    Csv(String, #[cause] csv::Error),
+    #[fail(display = "flag rule file <{}> was not a .toml or .csv file", _0)]
+    UnkFormat(String),
+}
+
+/// A Caution Flag code used by the MPS database
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum FlagCode {
+    O,
+    W,
+    F,
+}
+
+impl FlagCode {
+    const ALL: [FlagCode; 3] = [FlagCode::O, FlagCode::W, FlagCode::F];
+
+    pub fn all() -> impl Iterator<Item = FlagCode> {
+        FlagCode::ALL.iter().cloned()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlagCode::O => "O",
+            FlagCode::W => "W",
+            FlagCode::F => "F",
+        }
+    }
+}
+
+impl fmt::Display for FlagCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for FlagCode {
+    type Err = FlagError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.trim();
+        FlagCode::all()
+            .find(|c| c.as_str().eq_ignore_ascii_case(code))
+            .ok_or_else(|| FlagError::UnkCode(code.to_string()))
+    }
+}
+
+/// A Caution Flag cell as it was entered, and the known codes in it. Codes are
+/// separated by commas, semicolons, or spaces, or run together in capitals like "OW".
+/// Free text like "OK" is an unknown code rather than an O flag, but it's still kept
+/// and written back out.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct CautionFlag {
+    text: String,
+    codes: BTreeSet<FlagCode>,
+}
+
+impl CautionFlag {
+    /// The cell as it was entered
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn codes(&self) -> impl Iterator<Item = FlagCode> + '_ {
+        self.codes.iter().cloned()
+    }
+
+    pub fn contains(&self, code: FlagCode) -> bool {
+        self.codes.contains(&code)
+    }
+
+    /// Check if the cell doesn't have any known codes
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+}
+
+impl FromStr for CautionFlag {
+    type Err = std::convert::Infallible;
+
+    /// Unknown codes are logged as a warning and left out of the codes
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut codes = BTreeSet::new();
+        for part in s.split([',', ';', ' ', '|']).filter(|c| !c.trim().is_empty()) {
+            match part.parse() {
+                Ok(code) => { codes.insert(code); },
+                Err(e) => match run_of_codes(part) {
+                    Some(run) => codes.extend(run),
+                    None => warn!("keeping Caution Flag <{}> as written, but not as a code: {}", s, e),
+                },
+            }
+        }
+
+        Ok(CautionFlag { text: s.trim().to_string(), codes })
+    }
+}
+
+/// Split codes written together, like "OW", which only counts if every letter
+/// is a capital code letter, so that words like "OK" or "of" aren't read as codes
+fn run_of_codes(part: &str) -> Option<Vec<FlagCode>> {
+    let part = part.trim();
+    if part.len() < 2 { return None }
+
+    part.chars()
+        .map(|c| FlagCode::all().find(|code| code.as_str().starts_with(c)))
+        .collect()
+}
+
+/// Written as it was entered
+impl fmt::Display for CautionFlag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Serialize for CautionFlag {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+    {
+        s.collect_str(self)
+    }
+}
+
+/// Which Caution Flag codes cause a row to be excluded
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ExclusionRules {
+    excluded: BTreeSet<FlagCode>,
+}
+
+impl Default for ExclusionRules {
+    /// Every known code excludes its row
+    fn default() -> Self {
+        ExclusionRules { excluded: FlagCode::all().collect() }
+    }
+}
+
+impl ExclusionRules {
+    /// Load rules from a `.toml` file of `code = true/false` pairs, or a `.csv` file with
+    /// `Flag` and `Exclude` columns. Codes that aren't in the file keep their default.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, FlagError> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+
+        let pairs: Vec<(String, bool)> = match ext.as_deref() {
+            Some("toml") => {
+                let contents = fs::read_to_string(path)
+                    .map_err(|e| FlagError::Io(name.clone(), e))?;
+                let table: HashMap<String, bool> = toml::from_str(&contents)
+                    .map_err(|e| FlagError::Toml(name.clone(), e))?;
+                table.into_iter().collect()
+            },
+            Some("csv") => {
+                let mut rdr = csv::Reader::from_path(path)
+                    .map_err(|e| FlagError::Csv(name.clone(), e))?;
+                rdr.deserialize()
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| FlagError::Csv(name.clone(), e))?
+            },
+            _ => return Err(FlagError::UnkFormat(name)),
+        };
+
+        let mut rules = ExclusionRules::default();
+        for (code, exclude) in pairs {
+            rules.set(code.parse()?, exclude);
+        }
+
+        Ok(rules)
+    }
+
+    pub fn set(&mut self, code: FlagCode, exclude: bool) {
+        if exclude {
+            self.excluded.insert(code);
+        } else {
+            self.excluded.remove(&code);
+        }
+    }
+
+    /// Check if a row with this flag should be excluded
+    pub fn excludes(&self, flag: &CautionFlag) -> bool {
+        flag.codes().any(|c| self.excluded.contains(&c))
+    }
+}
+
+/// Read a known Exclude mark: "X" (or yes/true/1) marks the row as excluded and
+/// no/false/0 doesn't. Other text, like a reason, is `None`.
+fn exclude_value(cell: &str) -> Option<bool> {
+    match cell.trim().to_lowercase().as_str() {
+        "" | "no" | "n" | "false" | "0" => Some(false),
+        "x" | "yes" | "y" | "true" | "1" | "exclude" | "excluded" => Some(true),
+        _ => None,
+    }
+}
+
+/// Read an Exclude cell, where text that isn't a known mark is logged and treated as excluded
+pub(crate) fn exclude_mark(cell: &str) -> bool {
+    exclude_value(cell).unwrap_or_else(|| {
+        warn!("treating unknown Exclude value <{}> as excluded", cell.trim());
+        true
+    })
+}
+
+impl Mifc {
+    /// The Exclude cell as it's written: the cell as it was entered, as long as it
+    /// still agrees with whether the row is excluded, or else "X" for an excluded row
+    pub fn exclude_cell(&self) -> Option<&str> {
+        match self.exclude_text.as_deref() {
+            Some(text) if exclude_value(text).unwrap_or(true) == self.exclude => Some(text),
+            _ if self.exclude => Some("X"),
+            _ => None,
+        }
+    }
+
+    /// Mark this row as excluded if its Caution Flag has a code that `rules` excludes.
+    /// Rows that are already excluded stay excluded.
+    pub fn apply_flag_rules(&mut self, rules: &ExclusionRules) {
+        if self.flag.as_ref().is_some_and(|f| rules.excludes(f)) {
+            self.exclude = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The known codes of a flag, in order
+    fn codes(s: &str) -> String {
+        let flag = s.parse::<CautionFlag>().unwrap();
+        flag.codes().map(|c| c.as_str()).collect::<Vec<_>>().join(",")
+    }
+
+    #[test]
+    fn parse_flags() {
+        let flag = |s: &str| s.parse::<CautionFlag>().unwrap();
+
+        assert_eq!(flag("O").codes().collect::<Vec<_>>(), vec![FlagCode::O]);
+        assert_eq!(codes("w, O"), "O,W");
+        assert_eq!(codes("F;W F"), "W,F");
+        assert!(flag("OK").is_empty());
+        assert!(flag("outlier").is_empty());
+        assert_eq!(codes("OK, F"), "F");
+
+        // codes run together
+        assert_eq!(codes("OW"), "O,W");
+        assert_eq!(codes("WF, O"), "O,W,F");
+        assert!(flag("of").is_empty());
+        assert!(flag("OWL").is_empty());
+
+        // the cell is kept as it was entered
+        assert_eq!(flag(" w, O ").to_string(), "w, O");
+        assert_eq!(flag("outlier").text(), "outlier");
+    }
+
+    #[test]
+    fn exclusion_rules() {
+        let flag = |s: &str| s.parse::<CautionFlag>().unwrap();
+        let mut rules = ExclusionRules::default();
+
+        assert!(rules.excludes(&flag("O")));
+        assert!(rules.excludes(&flag("W")));
+        assert!(!rules.excludes(&flag("OK")));
+        assert!(rules.excludes(&flag("OW")));
+
+        rules.set(FlagCode::W, false);
+        assert!(!rules.excludes(&flag("W")));
+        assert!(rules.excludes(&flag("W,F")));
+    }
+
+    #[test]
+    fn exclude_cells_are_kept() {
+        let mut row: Mifc = csv::Reader::from_reader("Chip ID,Method/Kit,Target/Analyte,Sample Location,\
+            Day,Hour,Minute,Value,Caution Flag,Exclude\n\
+            C1,ELISA,Albumin,effluent,1,0,0,1.5,OK,bubble in well\n".as_bytes())
+            .deserialize().next().unwrap().unwrap();
+        assert!(row.exclude);
+        assert_eq!(row.exclude_cell(), Some("bubble in well"));
+        assert_eq!(row.flag.as_ref().map(CautionFlag::text), Some("OK"));
+        assert!(row.flag.as_ref().unwrap().is_empty());
+
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        wtr.serialize(&row).unwrap();
+        let output = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert!(output.lines().nth(1).unwrap().contains(",OK,bubble in well,"), "{}", output);
+
+        // an Exclude cell that no longer agrees with the row is replaced
+        row.exclude_text = Some("no".to_string());
+        assert_eq!(row.exclude_cell(), Some("X"));
+        row.exclude = false;
+        assert_eq!(row.exclude_cell(), Some("no"));
+        row.exclude_text = Some("bubble in well".to_string());
+        assert_eq!(row.exclude_cell(), None);
+    }
+
+    #[test]
+    fn exclude_cells() {
+        for cell in ["X", "x ", "yes", "1", "maybe"].iter() {
+            assert!(exclude_mark(cell), "{} should exclude", cell);
+        }
+        for cell in ["", "  ", "no", "0", "False"].iter() {
+            assert!(!exclude_mark(cell), "{} should not exclude", cell);
+        }
+    }
+}
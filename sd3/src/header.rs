@@ -0,0 +1,285 @@
+//! Matching the headers of an input file to SD3 columns. Headers match when
+//! they're the same after trimming, ignoring case and repeated whitespace, and
+//! treating the micro sign (U+00B5), Greek mu (U+03BC), and "u" as the same
+//! character. Other spellings can be added as column aliases.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use failure::{Fail};
+use crate::layout::{self, Layout};
+
+#[derive(Debug, Fail)]
+pub enum ColumnAliasError {
+    #[fail(display = "couldn't read column alias file <{}>", _0)]
+    Io(String, #[cause] std::io::Error),
+    #[fail(display = "couldn't parse TOML column alias file <{}>", _0)]
+    Toml(String, #[cause] toml::de::Error),
+    #[fail(display = "couldn't parse CSV column alias file <{}>", _0)]
+    Csv(String, #[cause] csv::Error),
+    #[fail(display = "column alias file <{}> was not a .toml or .csv file", _0)]
+    UnkFormat(String),
+    #[fail(display = "alias <{}> points to unknown column <{}>", _0, _1)]
+    UnkColumn(String, String),
+}
+
+/// Put a header into the form used for matching: trimmed, lowercase, with single
+/// spaces, and any micro sign written as "u"
+pub fn normalize(header: &str) -> String {
+    header.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .map(|c| if c == 'µ' || c == 'μ' { 'u' } else { c })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The SD3 column with this header, after normalization
+fn known_column(header: &str) -> Option<&'static str> {
+    let key = normalize(header);
+    layout::known_columns().find(|c| normalize(c) == key)
+}
+
+/// Other spellings of SD3 column headers
+#[derive(Debug, Clone, Default)]
+pub struct ColumnAliases {
+    /// Columns keyed by the `normalize`d alias
+    aliases: HashMap<String, &'static str>,
+}
+
+impl ColumnAliases {
+    pub fn new() -> Self {
+        ColumnAliases::default()
+    }
+
+    /// Load aliases from a `.toml` file of `"alias" = "column"` pairs, or
+    /// a `.csv` file with `Alias` and `Column` columns
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ColumnAliasError> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+
+        let pairs: Vec<(String, String)> = match ext.as_deref() {
+            Some("toml") => {
+                let contents = fs::read_to_string(path)
+                    .map_err(|e| ColumnAliasError::Io(name.clone(), e))?;
+                let table: HashMap<String, String> = toml::from_str(&contents)
+                    .map_err(|e| ColumnAliasError::Toml(name.clone(), e))?;
+                table.into_iter().collect()
+            },
+            Some("csv") => {
+                let mut rdr = csv::Reader::from_path(path)
+                    .map_err(|e| ColumnAliasError::Csv(name.clone(), e))?;
+                rdr.deserialize()
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| ColumnAliasError::Csv(name.clone(), e))?
+            },
+            _ => return Err(ColumnAliasError::UnkFormat(name)),
+        };
+
+        let mut aliases = ColumnAliases::new();
+        for (alias, column) in pairs.iter() {
+            aliases.add(alias, column)?;
+        }
+
+        Ok(aliases)
+    }
+
+    /// Register `alias` as another header for `column`, which must be an SD3 column
+    pub fn add(&mut self, alias: &str, column: &str) -> Result<(), ColumnAliasError> {
+        let column = known_column(column)
+            .ok_or_else(|| ColumnAliasError::UnkColumn(alias.to_string(), column.to_string()))?;
+        self.aliases.insert(normalize(alias), column);
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.aliases.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty()
+    }
+
+    fn get(&self, header: &str) -> Option<&'static str> {
+        self.aliases.get(&normalize(header)).cloned()
+    }
+}
+
+/// How a header was matched to a column
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MatchKind {
+    /// Spelled exactly like the column
+    Exact,
+    /// Spelled like the column after normalization
+    Normalized,
+    /// A column alias
+    Alias,
+    /// Not an SD3 column, or a column that an earlier header already matched
+    Unknown,
+}
+
+/// The column that one header was matched to
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderMatch {
+    /// The header as it was written in the file
+    pub source: String,
+    pub column: Option<&'static str>,
+    pub kind: MatchKind,
+}
+
+impl fmt::Display for HeaderMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.column, self.kind) {
+            (Some(column), MatchKind::Alias) => write!(f, "<{}> was read as <{}> (alias)", self.source, column),
+            (Some(column), _) => write!(f, "<{}> was read as <{}>", self.source, column),
+            (None, _) => write!(f, "<{}> is not a known column", self.source),
+        }
+    }
+}
+
+/// The columns matched to each header of a file, in order
+#[derive(Debug, Clone, Default)]
+pub struct HeaderMap {
+    matches: Vec<HeaderMatch>,
+}
+
+impl HeaderMap {
+    /// Match each header to a column. Exact matches are found first, so that a
+    /// normalized match or alias never takes a column from an exact match.
+    pub fn new<S: AsRef<str>>(headers: &[S], aliases: &ColumnAliases) -> Self {
+        let mut matches: Vec<HeaderMatch> = headers.iter()
+            .map(|h| {
+                let source = h.as_ref().to_string();
+                let column = layout::known_columns().find(|c| *c == source);
+                let kind = if column.is_some() { MatchKind::Exact } else { MatchKind::Unknown };
+                HeaderMatch { source, column, kind }
+            })
+            .collect();
+
+        for i in 0..matches.len() {
+            if matches[i].kind != MatchKind::Unknown { continue; }
+
+            let found = known_column(&matches[i].source).map(|c| (c, MatchKind::Normalized))
+                .or_else(|| aliases.get(&matches[i].source).map(|c| (c, MatchKind::Alias)));
+            if let Some((column, kind)) = found {
+                if matches.iter().all(|m| m.column != Some(column)) {
+                    matches[i].column = Some(column);
+                    matches[i].kind = kind;
+                }
+            }
+        }
+
+        HeaderMap { matches }
+    }
+
+    pub fn matches(&self) -> &[HeaderMatch] {
+        &self.matches
+    }
+
+    /// Headers that were matched to a column with a different spelling
+    pub fn renamed(&self) -> impl Iterator<Item = &HeaderMatch> {
+        self.matches.iter().filter(|m| m.kind == MatchKind::Normalized || m.kind == MatchKind::Alias)
+    }
+
+    /// The headers with each matched column in place of its source spelling
+    pub fn headers(&self) -> Vec<String> {
+        self.matches.iter()
+            .map(|m| m.column.map_or_else(|| m.source.clone(), str::to_string))
+            .collect()
+    }
+
+    /// The index and trimmed header of each column that isn't a column of `layout`, skipping
+    /// blank headers. Columns of other layouts, like a "Location" column in a MIFC file, are extra too.
+    pub fn extra(&self, layout: Layout) -> impl Iterator<Item = (usize, &str)> {
+        self.matches.iter()
+            .enumerate()
+            .filter(move |(_, m)| m.column.is_none_or(|c| !layout.has_column(c)))
+            .map(|(i, m)| (i, m.source.trim()))
+            .filter(|(_, h)| !h.is_empty())
+    }
+}
+
+/// The cells of a row's columns that aren't SD3 columns, like an operator or lot number,
+/// in the order they were read
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtraColumns(Vec<(String, String)>);
+
+impl ExtraColumns {
+    pub fn new() -> Self {
+        ExtraColumns::default()
+    }
+
+    pub fn push<H: Into<String>, V: Into<String>>(&mut self, header: H, value: V) {
+        self.0.push((header.into(), value.into()));
+    }
+
+    /// The value of the first column with this header
+    pub fn get(&self, header: &str) -> Option<&str> {
+        self.0.iter().find(|(h, _)| h == header).map(|(_, v)| v.as_str())
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|(h, _)| h.as_str())
+    }
+
+    /// Each (header, value) pair, in order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(h, v)| (h.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A record that can keep the cells of columns that aren't part of it
+pub trait KeepExtra {
+    /// The layout of the record's own columns; every other column is extra
+    fn layout() -> Layout;
+    fn extra_mut(&mut self) -> &mut ExtraColumns;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized_headers() {
+        assert_eq!(normalize("  Chip  Id "), "chip id");
+        assert_eq!(normalize("Sample Volume (µL)"), normalize("Sample Volume (uL)"));
+        assert_eq!(normalize("Sample Volume (μL)"), normalize("sample volume (UL)"));
+    }
+
+    #[test]
+    fn match_headers() {
+        let mut aliases = ColumnAliases::new();
+        aliases.add("Analyte", "target/analyte").unwrap();
+        assert!(aliases.add("Operator", "Person").is_err());
+
+        let headers = ["Chip Id ", "Analyte", "Sample Volume (uL)", "Operator", "Chip ID", "Time"];
+        let map = HeaderMap::new(&headers, &aliases);
+        let kinds: Vec<_> = map.matches().iter().map(|m| m.kind).collect();
+
+        assert_eq!(kinds, vec![
+            MatchKind::Unknown, MatchKind::Alias, MatchKind::Normalized,
+            MatchKind::Unknown, MatchKind::Exact, MatchKind::Exact,
+        ]);
+        assert_eq!(map.headers(), vec![
+            "Chip Id ", "Target/Analyte", "Sample Volume (µL)", "Operator", "Chip ID", "Time",
+        ]);
+        assert_eq!(map.renamed().count(), 2);
+        assert_eq!(map.matches()[2].to_string(), "<Sample Volume (uL)> was read as <Sample Volume (µL)>");
+        assert_eq!(map.extra(Layout::CmpdDit).collect::<Vec<_>>(), vec![(0, "Chip Id"), (3, "Operator")]);
+        // a MIFC record doesn't have the CMPD columns, so they're extra
+        assert_eq!(map.extra(Layout::Mifc).collect::<Vec<_>>(), vec![
+            (0, "Chip Id"), (2, "Sample Volume (uL)"), (3, "Operator"), (5, "Time"),
+        ]);
+    }
+}
@@ -0,0 +1,181 @@
+//! Normalization info kept apart from the data, like a chip log with a row of
+//! sample durations, volumes, and cell counts for each chip and day. The info is
+//! joined onto MIFC rows by Chip ID and time.
+use std::collections::HashMap;
+use std::path::Path;
+use failure::{Fail};
+use crate::header::ColumnAliases;
+use crate::layout::Layout;
+use crate::lookup::{self, TableError, key};
+use crate::mifc::{MifcNorm, Normalization};
+use crate::time::MifcTime;
+
+#[derive(Debug, Fail)]
+pub enum NormInfoError {
+    #[fail(display = "couldn't read normalization info")]
+    Table(#[cause] TableError),
+    #[fail(display = "no normalization info for chip <{}> at time {}", _0, _1)]
+    Unmatched(String, MifcTime),
+}
+
+impl From<TableError> for NormInfoError {
+    fn from(e: TableError) -> Self {
+        NormInfoError::Table(e)
+    }
+}
+
+/// Normalization info by Chip ID and time. A row gets the info of its chip with the
+/// nearest time, as long as it's within the tolerance, which is none by default.
+/// Chip IDs are matched ignoring case and surrounding whitespace.
+#[derive(Debug, Default)]
+pub struct NormInfoTable {
+    chips: HashMap<String, Vec<(MifcTime, Normalization)>>,
+    tolerance: MifcTime,
+}
+
+impl NormInfoTable {
+    pub fn new() -> Self {
+        NormInfoTable::default()
+    }
+
+    /// Load the info from every normalization info sheet of a CSV or workbook file,
+    /// which has `Chip ID` and `Day` columns, optional `Hour` and `Minute` columns,
+    /// and the same normalization columns as a `MifcNorm` row
+    pub fn from_path<P: AsRef<Path>>(path: P, aliases: &ColumnAliases) -> Result<Self, NormInfoError> {
+        let mut table = NormInfoTable::new();
+        lookup::read_chip_times(path.as_ref(), aliases, Layout::NormInfo, |_, chip, time, info| {
+            Ok::<_, NormInfoError>(table.insert(chip, time, info))
+        })?;
+
+        Ok(table)
+    }
+
+    /// Match rows to info with a time up to `tolerance` away from theirs
+    pub fn with_tolerance(self, tolerance: MifcTime) -> Self {
+        NormInfoTable { tolerance, ..self }
+    }
+
+    /// Add the info of a chip at a time, returning `false` if the chip already had info at that time
+    pub(crate) fn insert(&mut self, chip: &str, time: MifcTime, info: Normalization) -> bool {
+        let entries = self.chips.entry(key(chip)).or_default();
+        if entries.iter().any(|(t, _)| *t == time) {
+            return false;
+        }
+        entries.push((time, info));
+        true
+    }
+
+    /// The number of chip and time entries
+    pub fn len(&self) -> usize {
+        self.chips.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chips.is_empty()
+    }
+
+    /// The info of `chip` with the nearest time to `time`, within the tolerance
+    fn find(&self, chip: &str, time: MifcTime) -> Option<&Normalization> {
+        let distance = |t: &MifcTime| (t.total_minutes() - time.total_minutes()).abs();
+
+        self.chips.get(&key(chip))?
+            .iter()
+            .filter(|(t, _)| distance(t) <= self.tolerance.total_minutes().abs())
+            .min_by(|(a, _), (b, _)| distance(a).total_cmp(&distance(b)))
+            .map(|(_, info)| info)
+    }
+}
+
+impl MifcNorm {
+    /// Fill in this row's normalization info from `table`, unless it has its own.
+    /// Returns if the info was filled in, or an error if the table didn't have any.
+    pub fn join_info(&mut self, table: &NormInfoTable) -> Result<bool, NormInfoError> {
+        if self.normal_info.is_some() { return Ok(false) }
+
+        let (chip, time) = (&self.mifc.id, self.mifc.time);
+        let info = table.find(chip, time)
+            .ok_or_else(|| NormInfoError::Unmatched(chip.clone(), time))?;
+        self.normal_info = Some(info.clone());
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::layout::LayoutError;
+
+    const INFO: &str = "Chip ID,Day,Hour,Duration Sample Collection (days),Duration Sample Collection (hours),\
+        Duration Sample Collection (minutes),Sample Volume,Sample Volume Unit,Estimated Cell Number\n\
+        C1,1,,1,0,0,100,µL,100000\n\
+        c1,2,,1,0,0,100,µL,50000\n\
+        C2,1,12,1,0,0,200,µL,100000\n";
+
+    fn write_info(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("sd3-info-{}-{}.csv", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn mifc_row(chip: &str, day: f64, hour: f64) -> MifcNorm {
+        let input = format!("Chip ID,Method/Kit,Target/Analyte,Sample Location,Day,Hour,Minute,Value,Value Unit\n\
+            {},ELISA,Albumin,effluent,{},{},0,12.5,ng/mL\n", chip, day, hour);
+        csv::Reader::from_reader(input.as_bytes()).deserialize().next().unwrap().unwrap()
+    }
+
+    #[test]
+    fn join_by_chip_and_time() {
+        let path = write_info("join", INFO);
+        let table = NormInfoTable::from_path(&path, &ColumnAliases::new()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(table.len(), 3);
+
+        let mut row = mifc_row(" C1", 2.0, 0.0);
+        assert!(row.join_info(&table).unwrap());
+        assert_eq!(row.normal_info.as_ref().unwrap().cell_count, Some(50000.0));
+        // rows with their own info keep it
+        assert!(!row.join_info(&table).unwrap());
+
+        match mifc_row("C2", 1.0, 0.0).join_info(&table) {
+            Err(NormInfoError::Unmatched(chip, _)) => assert_eq!(chip, "C2"),
+            other => panic!("expected no match without a tolerance, got {:?}", other),
+        }
+        match mifc_row("C3", 1.0, 0.0).join_info(&table) {
+            Err(e @ NormInfoError::Unmatched(..)) => assert_eq!(e.to_string(), "no normalization info for chip <C3> at time 1.0.0"),
+            other => panic!("expected no match for an unknown chip, got {:?}", other),
+        }
+
+        let table = table.with_tolerance("1d".parse().unwrap());
+        let mut row = mifc_row("C2", 1.0, 0.0);
+        assert!(row.join_info(&table).unwrap());
+        assert_eq!(row.normal_info.as_ref().unwrap().sample_volume, 200.0);
+        // the nearest time wins
+        let mut row = mifc_row("C1", 1.0, 13.0);
+        assert!(row.join_info(&table).unwrap());
+        assert_eq!(row.normal_info.as_ref().unwrap().cell_count, Some(50000.0));
+    }
+
+    #[test]
+    fn bad_info_files() {
+        let path = write_info("dup", &format!("{}C2,1,12,1,0,0,250,µL,90000\n", INFO));
+        let result = NormInfoTable::from_path(&path, &ColumnAliases::new());
+        fs::remove_file(&path).unwrap();
+        match result {
+            Err(NormInfoError::Table(TableError::Duplicate(loc, chip, _))) => {
+                assert_eq!(chip, "C2");
+                assert_eq!(loc.row, 5);
+            },
+            other => panic!("expected a duplicate entry, got {:?}", other),
+        }
+
+        let path = write_info("mifc", "Chip ID,Method/Kit,Target/Analyte,Sample Location,Day,Hour,Minute\n");
+        let result = NormInfoTable::from_path(&path, &ColumnAliases::new());
+        fs::remove_file(&path).unwrap();
+        match result {
+            Err(NormInfoError::Table(TableError::NoSheet(_, Layout::NormInfo, LayoutError::Wrong { found: Layout::Mifc, .. }))) => (),
+            other => panic!("expected a MIFC file to not be normalization info, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,456 @@
+//! Reading SD3 records from CSV files and Excel or OpenDocument workbooks. Every
+//! sheet of every format is read the same way, and each record comes with
+//! the file, sheet, and row that it was read from.
+use std::fmt;
+use std::path::{Path, PathBuf};
+use calamine::{DataType, Range, RangeDeserializerBuilder, Reader, Sheets, open_workbook};
+use failure::{Fail};
+use serde::de::DeserializeOwned;
+use crate::header::{ColumnAliases, ExtraColumns, HeaderMap, KeepExtra};
+use crate::layout::Layout;
+
+#[derive(Debug, Fail)]
+pub enum ReadError {
+    #[fail(display = "<{}> is not a CSV, XLSX, XLSM, XLSB, XLS, or ODS file", _0)]
+    UnkFormat(String),
+    #[fail(display = "couldn't open workbook <{}>", _0)]
+    Workbook(String, #[cause] calamine::Error),
+    #[fail(display = "couldn't read sheet <{}> in <{}>", _1, _0)]
+    Sheet(String, String, #[cause] calamine::Error),
+    #[fail(display = "couldn't open CSV file <{}>", _0)]
+    Csv(String, #[cause] csv::Error),
+    #[fail(display = "couldn't read the header in {}: {}", _0, _1)]
+    Header(Location, #[cause] calamine::DeError),
+    #[fail(display = "couldn't read {}: {}", _0, _1)]
+    CsvRow(Location, #[cause] csv::Error),
+    #[fail(display = "couldn't read {}: {}", _0, _1)]
+    SheetRow(Location, #[cause] calamine::DeError),
+}
+
+impl ReadError {
+    /// Where the error happened, if it was in a row
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            ReadError::Header(loc, _)
+            | ReadError::CsvRow(loc, _)
+            | ReadError::SheetRow(loc, _) => Some(loc),
+            _ => None,
+        }
+    }
+}
+
+/// A file format that records can be read from
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Format {
+    Csv,
+    Xlsx,
+    Xlsb,
+    Xls,
+    Ods,
+}
+
+impl Format {
+    /// Guess a file's format from its extension, ignoring case
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_string_lossy().to_lowercase();
+        match ext.as_str() {
+            "csv" => Some(Format::Csv),
+            "xlsx" | "xlsm" | "xlam" => Some(Format::Xlsx),
+            "xlsb" => Some(Format::Xlsb),
+            "xls" | "xla" => Some(Format::Xls),
+            "ods" => Some(Format::Ods),
+            _ => None,
+        }
+    }
+}
+
+/// Where a record was read from. Rows are numbered as they are in a
+/// spreadsheet program, so the header is row 1 in most files.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Location {
+    pub file: PathBuf,
+    /// The sheet name, for workbooks
+    pub sheet: Option<String>,
+    pub row: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.sheet.as_ref() {
+            Some(sheet) => write!(f, "row {} of sheet <{}> in <{}>", self.row, sheet, self.file.display()),
+            None => write!(f, "row {} of <{}>", self.row, self.file.display()),
+        }
+    }
+}
+
+/// The records read from a sheet
+pub type Records<'s, T> = Box<dyn Iterator<Item = Result<Located<T>, ReadError>> + 's>;
+type RowsWithExtra<'s, T> = Box<dyn Iterator<Item = Result<(Located<T>, ExtraColumns), ReadError>> + 's>;
+
+/// A record and where it was read from
+#[derive(Debug, Clone)]
+pub struct Located<T> {
+    pub location: Location,
+    pub record: T,
+}
+
+/// An opened input file
+pub struct InputFile {
+    path: PathBuf,
+    source: Source,
+    aliases: ColumnAliases,
+}
+
+enum Source {
+    Csv,
+    Workbook(Box<Sheets>),
+}
+
+/// Open a CSV file or workbook, picking the format from the file extension
+pub fn open<P: AsRef<Path>>(path: P) -> Result<InputFile, ReadError> {
+    let path = path.as_ref();
+    let name = || path.display().to_string();
+    let wb_err = |e| ReadError::Workbook(name(), e);
+
+    let source = match Format::from_path(path).ok_or_else(|| ReadError::UnkFormat(name()))? {
+        Format::Csv => Source::Csv,
+        Format::Xlsx => Sheets::Xlsx(open_workbook(path).map_err(|e| wb_err(calamine::Error::Xlsx(e)))?).into(),
+        Format::Xlsb => Sheets::Xlsb(open_workbook(path).map_err(|e| wb_err(calamine::Error::Xlsb(e)))?).into(),
+        Format::Xls => Sheets::Xls(open_workbook(path).map_err(|e| wb_err(calamine::Error::Xls(e)))?).into(),
+        Format::Ods => Sheets::Ods(open_workbook(path).map_err(|e| wb_err(calamine::Error::Ods(e)))?).into(),
+    };
+
+    Ok(InputFile { path: path.to_path_buf(), source, aliases: ColumnAliases::new() })
+}
+
+impl From<Sheets> for Source {
+    fn from(wb: Sheets) -> Self {
+        Source::Workbook(Box::new(wb))
+    }
+}
+
+impl InputFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Match headers to columns with these aliases, as well as by their normalized spelling
+    pub fn with_aliases(mut self, aliases: &ColumnAliases) -> Self {
+        self.aliases = aliases.clone();
+        self
+    }
+
+    /// Names of the sheets in a workbook. A CSV file has one unnamed sheet, so this is empty.
+    pub fn sheet_names(&self) -> Vec<String> {
+        match &self.source {
+            Source::Csv => Vec::new(),
+            Source::Workbook(wb) => wb.sheet_names().to_vec(),
+        }
+    }
+
+    /// Read every sheet in the file, in order, and match each sheet's headers to columns.
+    /// A CSV file is a single unnamed sheet.
+    pub fn sheets(self) -> impl Iterator<Item = Result<Sheet, ReadError>> {
+        let names = self.sheet_names();
+        let InputFile { path, source, aliases } = self;
+
+        let sheets: Box<dyn Iterator<Item = Result<Sheet, ReadError>>> = match source {
+            Source::Csv => Box::new(std::iter::once(csv_sheet(path, &aliases))),
+            Source::Workbook(mut wb) => Box::new(names.into_iter().map(move |name| {
+                let mut range = match wb.worksheet_range(&name) {
+                    Some(Ok(range)) => range,
+                    Some(Err(e)) => return Err(ReadError::Sheet(path.display().to_string(), name, e)),
+                    None => Range::empty(),
+                };
+                let headers: Vec<String> = range.rows()
+                    .next()
+                    .map(|row| row.iter().map(DataType::to_string).collect())
+                    .unwrap_or_default();
+                let columns = HeaderMap::new(&headers, &aliases);
+                if let Some((row, col)) = range.start() {
+                    let matched = columns.matches().iter()
+                        .enumerate()
+                        .filter_map(|(i, m)| m.column.map(|c| (i as u32, c)));
+                    for (i, column) in matched {
+                        range.set_value((row, col + i), DataType::String(column.to_string()));
+                    }
+                }
+
+                Ok(Sheet { file: path.clone(), name: Some(name), data: Some(range), columns })
+            })),
+        };
+
+        sheets
+    }
+}
+
+/// Read a CSV file's header row as a sheet
+fn csv_sheet(path: PathBuf, aliases: &ColumnAliases) -> Result<Sheet, ReadError> {
+    let mut rdr = csv::Reader::from_path(&path)
+        .map_err(|e| ReadError::Csv(path.display().to_string(), e))?;
+    let location = Location { file: path.clone(), sheet: None, row: 1 };
+    let headers: Vec<String> = rdr.byte_headers()
+        .map_err(|e| ReadError::CsvRow(location, e))?
+        .iter()
+        .map(|h| match std::str::from_utf8(h) {
+            Ok(h) => h.to_string(),
+            // most likely Latin-1, which Excel uses for CSV files on Windows
+            Err(_) => h.iter().cloned().map(char::from).collect(),
+        })
+        .collect();
+    let columns = HeaderMap::new(&headers, aliases);
+
+    Ok(Sheet { file: path, name: None, data: None, columns })
+}
+
+/// One sheet of an input file: a workbook sheet, or an entire CSV file
+pub struct Sheet {
+    file: PathBuf,
+    name: Option<String>,
+    /// Cells of a workbook sheet, with matched headers; CSV files are read as records are
+    data: Option<Range<DataType>>,
+    columns: HeaderMap,
+}
+
+impl Sheet {
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// The sheet name, for workbooks
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// How each header was matched to a column
+    pub fn columns(&self) -> &HeaderMap {
+        &self.columns
+    }
+
+    /// The sheet's headers, with each matched column in place of its source spelling.
+    /// Cells that aren't text are read as they're shown, and an empty sheet has no headers.
+    pub fn headers(&self) -> Vec<String> {
+        self.columns.headers()
+    }
+
+    /// Read the sheet's header, then deserialize each row after it as a `T`
+    pub fn records<'s, T>(&'s self) -> Result<Records<'s, T>, ReadError>
+    where T: DeserializeOwned + 's
+    {
+        let rows = self.rows(None)?.map(|result| result.map(|(located, _)| located));
+        Ok(Box::new(rows))
+    }
+
+    /// Read each row like `records`, keeping the cells of any columns that
+    /// aren't columns of the record's layout in the record
+    pub fn records_with_extra<'s, T>(&'s self) -> Result<Records<'s, T>, ReadError>
+    where T: DeserializeOwned + KeepExtra + 's
+    {
+        let rows = self.rows::<T>(Some(T::layout()))?.map(|result| result.map(|(mut located, extra)| {
+            *located.record.extra_mut() = extra;
+            located
+        }));
+        Ok(Box::new(rows))
+    }
+
+    /// Deserialize each row after the header, along with the cells of the columns that
+    /// aren't in `layout`, if there is one
+    fn rows<'s, T>(&'s self, layout: Option<Layout>) -> Result<RowsWithExtra<'s, T>, ReadError>
+    where T: DeserializeOwned + 's
+    {
+        let location = move |row| Location { file: self.file.clone(), sheet: self.name.clone(), row };
+        let extra: Vec<(usize, String)> = layout.into_iter()
+            .flat_map(|layout| self.columns.extra(layout))
+            .map(|(i, h)| (i, h.to_string()))
+            .collect();
+        let extra_cells = move |cell: &dyn Fn(usize) -> Option<String>| {
+            let mut cells = ExtraColumns::new();
+            for (i, header) in extra.iter() {
+                cells.push(header.as_str(), cell(*i).unwrap_or_default());
+            }
+            cells
+        };
+
+        match self.data.as_ref() {
+            None => {
+                let mut rdr = csv::Reader::from_path(&self.file)
+                    .map_err(|e| ReadError::Csv(self.file.display().to_string(), e))?;
+                // read the header row first; headers that aren't UTF-8 are replaced by their decoded spelling too
+                if rdr.headers().is_err() || self.columns.renamed().next().is_some() {
+                    rdr.set_headers(self.columns.headers().into());
+                }
+                let headers = rdr.headers().ok().map(|h| h.as_byte_record().clone());
+                let rows = rdr.into_byte_records()
+                    .enumerate()
+                    .map(move |(i, result)| {
+                        let location = location(i + 2);
+                        let row = match result {
+                            Ok(row) => row,
+                            Err(e) => return Err(ReadError::CsvRow(location, e)),
+                        };
+                        match row.deserialize(headers.as_ref()) {
+                            Ok(record) => {
+                                let cells = extra_cells(&|c| row.get(c).map(|v| String::from_utf8_lossy(v).into_owned()));
+                                Ok((Located { location, record }, cells))
+                            },
+                            Err(e) => Err(ReadError::CsvRow(location, e)),
+                        }
+                    });
+
+                Ok(Box::new(rows))
+            },
+            Some(range) => {
+                let header_row = range.start().map_or(0, |(r, _)| r as usize) + 1;
+                // the deserializer reads every row after the header, in order
+                let mut cells = range.rows().skip(1);
+                let rows = RangeDeserializerBuilder::new()
+                    .has_headers(true)
+                    .from_range(range)
+                    .map_err(|e| ReadError::Header(location(header_row), e))?
+                    .enumerate()
+                    .map(move |(i, result)| {
+                        let row = cells.next().unwrap_or(&[]);
+                        match result {
+                            Ok(record) => {
+                                let cells = extra_cells(&|c| row.get(c).map(DataType::to_string));
+                                Ok((Located { location: location(header_row + i + 1), record }, cells))
+                            },
+                            Err(e) => Err(ReadError::SheetRow(location(header_row + i + 1), e)),
+                        }
+                    });
+
+                Ok(Box::new(rows))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::Mifc;
+
+    #[test]
+    fn formats_from_extensions() {
+        assert_eq!(Format::from_path("data.csv"), Some(Format::Csv));
+        assert_eq!(Format::from_path("data.XLSX"), Some(Format::Xlsx));
+        assert_eq!(Format::from_path("dir/data.xlsm"), Some(Format::Xlsx));
+        assert_eq!(Format::from_path("data.ods"), Some(Format::Ods));
+        assert_eq!(Format::from_path("data.txt"), None);
+        assert_eq!(Format::from_path("data"), None);
+    }
+
+    #[test]
+    fn csv_records_have_locations() {
+        let path = std::env::temp_dir().join(format!("sd3-io-{}.csv", std::process::id()));
+        fs::write(&path, "Chip ID,Method/Kit,Target/Analyte,Sample Location,Day,Hour,Minute,Value,Value Unit\n\
+            C1,ELISA,Albumin,effluent,1,0,0,12.5,ng/mL\n\
+            C2,ELISA,Albumin,effluent,one,0,0,10,ng/mL\n\
+            C3,ELISA,Albumin,effluent,2,0,0,,\n").unwrap();
+
+        let file = open(&path).unwrap();
+        assert!(file.sheet_names().is_empty());
+        let sheets: Vec<Sheet> = file.sheets().collect::<Result<_, _>>().unwrap();
+        assert_eq!(sheets.len(), 1);
+        assert_eq!(sheets[0].name(), None);
+
+        assert_eq!(sheets[0].headers()[..3], ["Chip ID", "Method/Kit", "Target/Analyte"]);
+        let rows: Vec<_> = sheets[0].records::<Mifc>().unwrap().collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(rows.len(), 3);
+        let first = rows[0].as_ref().unwrap();
+        assert_eq!(first.record.id, "C1");
+        assert_eq!(first.location.row, 2);
+        assert_eq!(first.location.sheet, None);
+        assert_eq!(rows[1].as_ref().unwrap_err().location().unwrap().row, 3);
+        assert_eq!(rows[2].as_ref().unwrap().location.row, 4);
+    }
+
+    #[test]
+    fn tolerant_csv_headers() {
+        let path = std::env::temp_dir().join(format!("sd3-io-headers-{}.csv", std::process::id()));
+        fs::write(&path, "chip id ,Method/Kit,Analyte,SAMPLE LOCATION,Day,Hour,Minute,Value,Value Unit\n\
+            C1,ELISA,Albumin,effluent,1,0,0,12.5,ng/mL\n").unwrap();
+
+        let mut aliases = ColumnAliases::new();
+        aliases.add("Analyte", "Target/Analyte").unwrap();
+        let sheet = open(&path).unwrap().with_aliases(&aliases).sheets().next().unwrap().unwrap();
+        let rows: Vec<_> = sheet.records::<Mifc>().unwrap().collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(sheet.columns().renamed().count(), 3);
+        assert_eq!(sheet.headers()[..4], ["Chip ID", "Method/Kit", "Target/Analyte", "Sample Location"]);
+        let row = &rows[0].as_ref().unwrap().record;
+        assert_eq!(row.id, "C1");
+        assert_eq!(row.target, "Albumin");
+    }
+
+    #[test]
+    fn extra_columns() {
+        let path = std::env::temp_dir().join(format!("sd3-io-extra-{}.csv", std::process::id()));
+        fs::write(&path, "Chip ID,Operator,Method/Kit,Target/Analyte,Sample Location,Day,Hour,Minute,,Lot\n\
+            C1,MC,ELISA,Albumin,effluent,1,0,0,,A7\n").unwrap();
+
+        let sheet = open(&path).unwrap().sheets().next().unwrap().unwrap();
+        let kept: Vec<_> = sheet.records_with_extra::<Mifc>().unwrap().collect();
+        let dropped: Vec<_> = sheet.records::<Mifc>().unwrap().collect();
+        fs::remove_file(&path).unwrap();
+
+        let extra = &kept[0].as_ref().unwrap().record.extra;
+        assert_eq!(extra.iter().collect::<Vec<_>>(), vec![("Operator", "MC"), ("Lot", "A7")]);
+        assert!(dropped[0].as_ref().unwrap().record.extra.is_empty());
+    }
+
+    #[test]
+    fn latin1_csv_headers() {
+        let path = std::env::temp_dir().join(format!("sd3-io-latin1-{}.csv", std::process::id()));
+        fs::write(&path, &b"Chip ID,Method/Kit,Target/Analyte,Sample Location,Day,Hour,Minute,Sample Volume (\xb5L),Lot\n\
+            C1,ELISA,Albumin,effluent,1,0,0,100,A7\n"[..]).unwrap();
+
+        let sheet = open(&path).unwrap().sheets().next().unwrap().unwrap();
+        let rows: Vec<_> = sheet.records_with_extra::<Mifc>().unwrap().collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(sheet.headers()[7], "Sample Volume (µL)");
+        let row = &rows[0].as_ref().unwrap().record;
+        assert_eq!(row.id, "C1");
+        // a CMPD column isn't a MIFC column, so it's passed through
+        assert_eq!(row.extra.iter().collect::<Vec<_>>(), vec![("Sample Volume (µL)", "100"), ("Lot", "A7")]);
+    }
+
+    #[test]
+    fn tolerant_sheet_headers() {
+        let path = std::env::temp_dir().join(format!("sd3-io-headers-{}.xlsx", std::process::id()));
+        let mut wb = rust_xlsxwriter::Workbook::new();
+        let sheet = wb.add_worksheet().set_name("Plate 1").unwrap();
+        let rows = [
+            ["Chip ID", " method/kit", "Target/Analyte", "Sample Location", "Day", "Hour", "Minute", "Lot"],
+            ["C1", "ELISA", "Albumin", "effluent", "1", "0", "0", "A7"],
+        ];
+        for (r, row) in rows.iter().enumerate() {
+            for (c, cell) in row.iter().enumerate() {
+                sheet.write_string(r as u32, c as u16, *cell).unwrap();
+            }
+        }
+        wb.save(&path).unwrap();
+
+        let sheet = open(&path).unwrap().sheets().next().unwrap().unwrap();
+        let rows: Vec<_> = sheet.records_with_extra::<Mifc>().unwrap().collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(sheet.columns().renamed().count(), 1);
+        let row = &rows[0].as_ref().unwrap().record;
+        assert_eq!(row.method, "ELISA");
+        assert_eq!(row.extra.get("Lot"), Some("A7"));
+    }
+
+    #[test]
+    fn unknown_format() {
+        match open("metadata.txt") {
+            Err(ReadError::UnkFormat(f)) => assert_eq!(f, "metadata.txt"),
+            _ => panic!("a .txt file should be an unknown format"),
+        }
+    }
+}
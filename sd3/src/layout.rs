@@ -0,0 +1,303 @@
+//! Recognizing which SD3 layout a file is from its header row, so that tools
+//! can stop early when given the wrong kind of file
+use std::fmt;
+use failure::{Fail};
+use crate::field::MifcField;
+
+/// The required columns of the normalization info in a `MifcNorm` file. The Estimated
+/// Cell Number is optional, since it can be interpolated from cell counts instead.
+const NORM_REQUIRED: [&str; 5] = [
+    "Duration Sample Collection (days)", "Duration Sample Collection (hours)",
+    "Duration Sample Collection (minutes)", "Sample Volume", "Sample Volume Unit",
+];
+const NORM_OPTIONAL: [&str; 4] = [
+    "Estimated Cell Number", "Molecular Weight (g/mol)", "Sample Volume Uncertainty",
+    "Estimated Cell Number Uncertainty",
+];
+/// A normalization info file has the normalization columns for each chip and time,
+/// where the time is a Day with optional Hour and Minute columns
+const INFO_REQUIRED: [&str; 2] = ["Chip ID", "Day"];
+const INFO_OPTIONAL: [&str; 2] = ["Hour", "Minute"];
+/// A cell count file has a series of counts for each chip, timed like a normalization info file
+const COUNTS_REQUIRED: [&str; 3] = ["Chip ID", "Day", "Estimated Cell Number"];
+const CMPD_REQUIRED: [&str; 6] = [
+    "Group Indicator", "Chip ID", "Time", "Method/Kit", "Target/Analyte", "Location",
+];
+const CMPD_OPTIONAL: [&str; 11] = [
+    "Result", "Result Unit", "Dilution", "Note (optional)", "Flag (optional)",
+    "Replicate (optional)", "TCTCxRef (optional)", "Cell Count", "Sample Duration",
+    "Sample Volume (µL)", "Uncertainty (optional)",
+];
+/// Compact files also have a "Time" column, which may have a unit like "Time [min]",
+/// and every other column belongs to a target
+const COMPACT_REQUIRED: [&str; 2] = ["Chip ID", "Control Chip"];
+const COMPACT_TIME: &str = "Time";
+
+/// Every column of every layout, besides the target columns of compact files
+pub(crate) fn known_columns() -> impl Iterator<Item = &'static str> {
+    MifcField::all()
+        .map(|f| f.header())
+        .chain(NORM_REQUIRED.iter().chain(NORM_OPTIONAL.iter()).cloned())
+        .chain(CMPD_REQUIRED.iter().chain(CMPD_OPTIONAL.iter()).cloned())
+        .chain(COMPACT_REQUIRED.iter().cloned())
+}
+
+#[derive(Debug, Fail)]
+pub enum LayoutError {
+    #[fail(display = "the header doesn't look like any SD3 file; expected a {} file", _0)]
+    Unknown(Layout),
+    #[fail(display = "this looks like a {} file, not a {} file{}", found, expected, hint)]
+    Wrong { expected: Layout, found: Layout, hint: Hint },
+    #[fail(display = "this {} file is missing column(s): {}", _0, _1)]
+    Missing(Layout, String),
+}
+
+/// A suggestion of which tool reads a layout, shown after a `LayoutError::Wrong`
+#[derive(Debug)]
+pub struct Hint(Option<&'static str>);
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(tool) => write!(f, "; did you mean to use {}?", tool),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The kinds of SD3 files
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Layout {
+    /// Plain MIFC
+    Mifc,
+    /// MIFC with normalization info
+    MifcNorm,
+    MifcImage,
+    /// Normalization info by chip and time, without any MIFC data
+    NormInfo,
+    /// Cell counts by chip and time
+    CellCounts,
+    /// Compound columnar data
+    CmpdDit,
+    /// One row per chip and time, with a column for each target
+    Compact,
+}
+
+impl Layout {
+    pub fn all() -> impl Iterator<Item = Layout> {
+        use self::Layout::*;
+        [Mifc, MifcNorm, MifcImage, NormInfo, CellCounts, CmpdDit, Compact].iter().cloned()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layout::Mifc => "MIFC",
+            Layout::MifcNorm => "MIFC + normalization",
+            Layout::MifcImage => "MIFC-Image",
+            Layout::NormInfo => "normalization info",
+            Layout::CellCounts => "cell count",
+            Layout::CmpdDit => "CMPD",
+            Layout::Compact => "compact",
+        }
+    }
+
+    /// The tool in this repository that reads this layout
+    pub fn tool(&self) -> Option<&'static str> {
+        match self {
+            Layout::Mifc => None,
+            Layout::MifcNorm => Some("sd3norm"),
+            Layout::MifcImage => Some("imgqc"),
+            Layout::NormInfo => Some("sd3norm --norm-info"),
+            Layout::CellCounts => Some("sd3norm --cell-counts"),
+            Layout::CmpdDit => Some("cmpdfmt"),
+            Layout::Compact => Some("decompact"),
+        }
+    }
+
+    /// Columns that every file of this layout has
+    fn required(&self) -> Vec<&'static str> {
+        let mifc = MifcField::MIFC.iter().filter(|f| f.is_required()).map(MifcField::header);
+        let image = MifcField::IMAGE.iter().filter(|f| f.is_required()).map(MifcField::header);
+
+        match self {
+            Layout::Mifc => mifc.collect(),
+            Layout::MifcNorm => mifc.chain(NORM_REQUIRED.iter().cloned()).collect(),
+            Layout::MifcImage => mifc.chain(image).collect(),
+            Layout::NormInfo => INFO_REQUIRED.iter().chain(NORM_REQUIRED.iter()).cloned().collect(),
+            Layout::CellCounts => COUNTS_REQUIRED.to_vec(),
+            Layout::CmpdDit => CMPD_REQUIRED.to_vec(),
+            Layout::Compact => COMPACT_REQUIRED.iter().cloned().chain(Some(COMPACT_TIME)).collect(),
+        }
+    }
+
+    /// Check if this layout has a column, besides its required ones
+    fn has_optional(&self, header: &str) -> bool {
+        let mifc = || MifcField::MIFC.iter().any(|f| f.header() == header);
+
+        match self {
+            Layout::Mifc => mifc(),
+            Layout::MifcNorm => mifc() || NORM_OPTIONAL.contains(&header),
+            Layout::MifcImage => MifcField::all().any(|f| f.header() == header),
+            Layout::NormInfo => INFO_OPTIONAL.contains(&header) || NORM_OPTIONAL.contains(&header),
+            Layout::CellCounts => INFO_OPTIONAL.contains(&header),
+            Layout::CmpdDit => CMPD_OPTIONAL.contains(&header),
+            Layout::Compact => true,
+        }
+    }
+
+    /// Check if this layout has a column, required or optional
+    pub(crate) fn has_column(&self, column: &str) -> bool {
+        self.required().contains(&column) || self.has_optional(column)
+    }
+
+    /// Compare a header row to this layout
+    fn compare<S: AsRef<str>>(self, headers: &[S]) -> HeaderReport {
+        let headers: Vec<&str> = headers.iter()
+            .map(|h| h.as_ref().trim())
+            .filter(|h| !h.is_empty())
+            .collect();
+        let required = self.required();
+        let has = |col: &str| match (self, col) {
+            (Layout::Compact, COMPACT_TIME) => headers.iter().any(|h| h.starts_with(COMPACT_TIME)),
+            _ => headers.contains(&col),
+        };
+
+        let missing: Vec<_> = required.iter().cloned().filter(|c| !has(c)).collect();
+        let unexpected = headers.iter()
+            .filter(|h| !required.contains(h) && !self.has_optional(h))
+            .map(|h| h.to_string())
+            .collect();
+        let found = required.len() - missing.len();
+
+        HeaderReport { layout: self, missing, unexpected, found }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// How well a header row matched a layout
+#[derive(Debug, Clone)]
+pub struct HeaderReport {
+    pub layout: Layout,
+    /// Required columns that the header doesn't have
+    pub missing: Vec<&'static str>,
+    /// Columns that the layout doesn't have
+    pub unexpected: Vec<String>,
+    /// The number of required columns that were found
+    found: usize,
+}
+
+impl HeaderReport {
+    /// Check if every required column was found
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// Find the layout that best matches a header row, which is the one with the
+/// fewest missing and unexpected columns. `None` means that no required column
+/// of any layout was found. Since any column of a compact file can be a target,
+/// compact files are only recognized when they have every required column.
+pub fn detect_layout<S: AsRef<str>>(headers: &[S]) -> Option<HeaderReport> {
+    Layout::all()
+        .map(|layout| layout.compare(headers))
+        .filter(|report| report.found > 0)
+        .filter(|report| report.layout != Layout::Compact || report.is_complete())
+        .min_by_key(|report| (report.missing.len() + report.unexpected.len(), report.missing.len()))
+}
+
+/// Check that a header row is the `expected` layout, with every required column,
+/// returning a report of any unexpected columns
+pub fn check_layout<S: AsRef<str>>(headers: &[S], expected: Layout) -> Result<HeaderReport, LayoutError> {
+    let report = expected.compare(headers);
+    if report.is_complete() {
+        return Ok(report);
+    }
+
+    match detect_layout(headers) {
+        Some(ref found) if found.layout != expected && found.is_complete() => Err(LayoutError::Wrong {
+            expected,
+            found: found.layout,
+            hint: Hint(found.layout.tool()),
+        }),
+        _ if report.found == 0 => Err(LayoutError::Unknown(expected)),
+        _ => Err(LayoutError::Missing(expected, report.missing.join(", "))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIFC: &str = "Chip ID,Assay Plate ID,Assay Well ID,Method/Kit,Target/Analyte,Subtarget,\
+        Sample Location,Day,Hour,Minute,Value,Value Unit,Caution Flag,Exclude,Notes,Replicate,\
+        Cross Reference";
+
+    fn split(header: &str) -> Vec<&str> {
+        header.split(',').collect()
+    }
+
+    #[test]
+    fn detect_layouts() {
+        let norm = format!("{},{},Estimated Cell Number Uncertainty", MIFC, NORM_REQUIRED.join(","));
+        let image = format!("{},Image File Name,Image Field,Image Magnification,Image Resolution,\
+            Image Resolution Unit,Image Sample Label,Image Color Mapping", MIFC);
+        let cmpd = "Group Indicator,Chip ID,Time,Method/Kit,Target/Analyte,Result,Result Unit,\
+            Dilution,Location,Note (optional)";
+        let compact = "Chip ID,Control Chip,Time [min],Notes,Albumin,Albumin [Flags]";
+        let info = format!("Chip ID,Day,Hour,{}", NORM_REQUIRED.join(","));
+        let counts = "Chip ID,Day,Hour,Minute,Estimated Cell Number";
+
+        let cases = [
+            (MIFC, Layout::Mifc), (&norm, Layout::MifcNorm), (&image, Layout::MifcImage),
+            (&info, Layout::NormInfo), (counts, Layout::CellCounts), (cmpd, Layout::CmpdDit),
+            (compact, Layout::Compact),
+        ];
+        for (header, layout) in cases.iter() {
+            let report = detect_layout(&split(header)).unwrap();
+            assert_eq!(report.layout, *layout);
+            assert!(report.is_complete());
+            assert!(report.unexpected.is_empty(), "{:?}", report);
+        }
+
+        assert!(detect_layout(&["Plate", "Well", "OD"]).is_none());
+    }
+
+    #[test]
+    fn missing_and_unexpected() {
+        let header = MIFC.replace("Sample Location,", "") + ", Operator ,";
+        let report = detect_layout(&split(&header)).unwrap();
+
+        assert_eq!(report.layout, Layout::Mifc);
+        assert_eq!(report.missing, vec!["Sample Location"]);
+        assert_eq!(report.unexpected, vec!["Operator".to_string()]);
+    }
+
+    #[test]
+    fn check_expected_layout() {
+        let cmpd = split("Group Indicator,Chip ID,Time,Method/Kit,Target/Analyte,Location");
+        assert!(check_layout(&cmpd, Layout::CmpdDit).is_ok());
+
+        match check_layout(&cmpd, Layout::MifcNorm) {
+            Err(e @ LayoutError::Wrong { .. }) => assert_eq!(e.to_string(),
+                "this looks like a CMPD file, not a MIFC + normalization file; did you mean to use cmpdfmt?"),
+            r => panic!("expected a wrong layout, not {:?}", r),
+        }
+        match check_layout(&split(MIFC), Layout::MifcNorm) {
+            Err(LayoutError::Wrong { found: Layout::Mifc, .. }) => (),
+            r => panic!("expected a plain MIFC layout, not {:?}", r),
+        }
+        match check_layout(&split(&MIFC.replace("Day,", "")), Layout::Mifc) {
+            Err(e @ LayoutError::Missing(..)) => assert_eq!(e.to_string(), "this MIFC file is missing column(s): Day"),
+            r => panic!("expected missing columns, not {:?}", r),
+        }
+        match check_layout(&["Plate", "Well"], Layout::MifcImage) {
+            Err(LayoutError::Unknown(Layout::MifcImage)) => (),
+            r => panic!("expected an unknown layout, not {:?}", r),
+        }
+    }
+}
@@ -3,9 +3,66 @@
 //! of TCTC data formats 
 mod mifc;
 mod cmpd;
+mod mw;
+mod norm;
+mod blank;
+mod info;
+mod cells;
+mod lookup;
+mod time;
+mod flag;
+mod field;
+mod validate;
+mod layout;
+mod header;
+mod de;
+pub mod io;
+mod write;
 
 pub use crate::mifc::Mifc as Mifc;
 pub use crate::mifc::MifcImage as MifcImage;
 pub use crate::mifc::MifcNorm as MifcNorm;
 pub use crate::mifc::MifcNormError as MifcNormError;
 pub use crate::cmpd::CmpdDit as CmpdDit;
+pub use crate::norm::NormBasis as NormBasis;
+pub use crate::norm::NormStrategy as NormStrategy;
+pub use crate::norm::NormStrategyError as NormStrategyError;
+pub use crate::blank::BlankRules as BlankRules;
+pub use crate::blank::Blanks as Blanks;
+pub use crate::blank::BlankError as BlankError;
+pub use crate::info::NormInfoTable as NormInfoTable;
+pub use crate::info::NormInfoError as NormInfoError;
+pub use crate::cells::CellCounts as CellCounts;
+pub use crate::cells::Interpolation as Interpolation;
+pub use crate::cells::CellCountError as CellCountError;
+pub use crate::lookup::TableError as TableError;
+pub use crate::mw::MolecularWeights as MolecularWeights;
+pub use crate::mw::MolWeightError as MolWeightError;
+pub use crate::time::MifcTime as MifcTime;
+pub use crate::time::TimeParseError as TimeParseError;
+pub use crate::flag::FlagCode as FlagCode;
+pub use crate::flag::CautionFlag as CautionFlag;
+pub use crate::flag::ExclusionRules as ExclusionRules;
+pub use crate::flag::FlagError as FlagError;
+pub use crate::field::MifcField as MifcField;
+pub use crate::field::UnkFieldError as UnkFieldError;
+pub use crate::validate::Issue as Issue;
+pub use crate::validate::IssueKind as IssueKind;
+pub use crate::validate::Severity as Severity;
+pub use crate::layout::Layout as Layout;
+pub use crate::layout::LayoutError as LayoutError;
+pub use crate::layout::HeaderReport as HeaderReport;
+pub use crate::layout::detect_layout as detect_layout;
+pub use crate::layout::check_layout as check_layout;
+pub use crate::header::ColumnAliases as ColumnAliases;
+pub use crate::header::ColumnAliasError as ColumnAliasError;
+pub use crate::header::HeaderMap as HeaderMap;
+pub use crate::header::HeaderMatch as HeaderMatch;
+pub use crate::header::MatchKind as MatchKind;
+pub use crate::header::ExtraColumns as ExtraColumns;
+pub use crate::header::KeepExtra as KeepExtra;
+pub use crate::write::MifcWriter as MifcWriter;
+pub use crate::write::CsvWriter as CsvWriter;
+pub use crate::write::XlsxWriter as XlsxWriter;
+pub use crate::write::OutputFormat as OutputFormat;
+pub use crate::write::WriteError as WriteError;
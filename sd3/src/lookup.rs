@@ -0,0 +1,86 @@
+//! Tables of values looked up by name, like molecular weights by target, or
+//! normalization info and cell counts by Chip ID and time
+use std::path::Path;
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use failure::{Fail};
+use crate::de::blank_as_none;
+use crate::header::ColumnAliases;
+use crate::io::{self, Location, ReadError};
+use crate::layout::{Layout, LayoutError, check_layout};
+use crate::time::MifcTime;
+
+#[derive(Debug, Fail)]
+pub enum TableError {
+    #[fail(display = "couldn't read the file")]
+    Read(#[cause] ReadError),
+    #[fail(display = "no sheet of <{}> had {} rows", _0, _1)]
+    NoSheet(String, Layout, #[cause] LayoutError),
+    #[fail(display = "{} doesn't have a Chip ID", _0)]
+    NoChipId(Location),
+    #[fail(display = "{} has the same Chip ID <{}> and time {} as an earlier row", _0, _1, _2)]
+    Duplicate(Location, String, MifcTime),
+}
+
+impl From<ReadError> for TableError {
+    fn from(e: ReadError) -> Self {
+        TableError::Read(e)
+    }
+}
+
+/// The key a name is looked up by, ignoring case and surrounding whitespace
+pub(crate) fn key(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// One row of a table by Chip ID and time, where the time is a Day with optional
+/// Hour and Minute columns
+#[derive(Debug, Deserialize)]
+struct ChipTimeRow<T> {
+    #[serde(rename = "Chip ID", default, deserialize_with = "blank_as_none")]
+    chip_id: Option<String>,
+    #[serde(rename = "Day")]
+    day: f64,
+    #[serde(rename = "Hour", default, deserialize_with = "blank_as_none")]
+    hour: Option<f64>,
+    #[serde(rename = "Minute", default, deserialize_with = "blank_as_none")]
+    min: Option<f64>,
+    #[serde(flatten)]
+    value: T,
+}
+
+/// Read the rows of every `layout` sheet of a CSV or workbook file, passing each row's
+/// Chip ID, time, and value to `insert`, which returns `false` if the chip already had
+/// a value at that time. Sheets of other layouts are skipped, unless no sheet is a `layout` sheet.
+pub(crate) fn read_chip_times<T, E, F>(path: &Path, aliases: &ColumnAliases, layout: Layout, mut insert: F) -> Result<(), E>
+where T: DeserializeOwned,
+      E: From<TableError>,
+      F: FnMut(&Location, &str, MifcTime, T) -> Result<bool, E>,
+{
+    let mut layout_error = None;
+    let mut found = false;
+
+    for sheet in io::open(path).map_err(TableError::from)?.with_aliases(aliases).sheets() {
+        let sheet = sheet.map_err(TableError::from)?;
+        if let Err(e) = check_layout(&sheet.headers(), layout) {
+            layout_error.get_or_insert(e);
+            continue;
+        }
+        found = true;
+
+        for row in sheet.records::<ChipTimeRow<T>>().map_err(TableError::from)? {
+            let io::Located { location, record } = row.map_err(TableError::from)?;
+            let chip = record.chip_id
+                .ok_or_else(|| TableError::NoChipId(location.clone()))?;
+            let time = MifcTime::from_dhm(record.day, record.hour.unwrap_or(0.0), record.min.unwrap_or(0.0));
+            if !insert(&location, &chip, time, record.value)? {
+                return Err(TableError::Duplicate(location, chip, time).into());
+            }
+        }
+    }
+
+    match layout_error {
+        Some(e) if !found => Err(TableError::NoSheet(path.display().to_string(), layout, e).into()),
+        _ => Ok(()),
+    }
+}
@@ -1,12 +1,19 @@
 use serde_derive::{Deserialize, Serialize};
 use failure::{Fail};
 use log::{trace};
-use units::{SIUnit, SIError, self};
+use units::{SIUnit, SIError, Uncertain, Unit, UnitType, self};
+use crate::mw::{MolecularWeights, MolWeightError};
+use crate::time::MifcTime;
+use crate::flag::{CautionFlag, ExclusionRules, exclude_mark};
+use crate::de::blank_as_none;
+use crate::header::{ExtraColumns, KeepExtra};
+use crate::layout::Layout;
+use crate::norm::NormStrategy;
 
 #[derive(Debug, Fail)]
 /// Errors that can occur during normalization of a `MifcNorm` into a `Mifc`
 pub enum MifcNormError {
-    #[fail(display = "row had a non-empty Exclude column")]
+    #[fail(display = "row was marked in the Exclude column")]
     Excluded,
     #[fail(display = "row did not have associated normalization info columns")]
     NoInfo,
@@ -16,51 +23,70 @@ pub enum MifcNormError {
     NoValueUnit,
     #[fail(display = "couldn't convert value unit into a known standarized unit")]
     UnkValueUnit(#[cause] SIError),
+    #[fail(display = "row had a molar Value Unit but no Molecular Weight (g/mol)")]
+    NoMolecularWeight,
+    #[fail(display = "row had a mass Value Unit but no Molecular Weight (g/mol) to normalize it into a molar unit")]
+    NoMolecularWeightForMolar,
+    #[fail(display = "couldn't convert between the row's units during normalization")]
+    UnitConversion(#[cause] SIError),
+    #[fail(display = "row's Value Unit <{}> is a {}, which can't be normalized as a mass", _0, _1)]
+    NotMassBased(Unit, UnitType),
+    #[fail(display = "row did not have an Estimated Cell Number to normalize per cell")]
+    NoCellCount,
 }
 impl From<SIError> for MifcNormError {
     fn from(e: SIError) -> Self {
         MifcNormError::UnkValueUnit(e)
     }
 }
-//TODO: Deserialize optional string fields with a null || "" = None checking function
 
 /// MIFC fields with additional normalization info. This struct can be used 
 /// to create a new MIFC row that has been normalized based on that additional info.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MifcNorm {
     #[serde(flatten)]
-    mifc: Mifc,
+    pub(crate) mifc: Mifc,
     #[serde(flatten)]
-    normal_info: Option<Normalization>,
+    pub(crate) normal_info: Option<Normalization>,
 }
 
 impl MifcNorm {
     /// Transform a `MifcNorm` into a `Mifc` by using the normalization information 
-    /// contained with the `MifcNorm` `struct`.
-    pub fn into_normalized(self) -> Result<Mifc, MifcNormError> {
-        if let Some(ref f) = self.mifc.exclude {
-            if f != "" { return Err(MifcNormError::Excluded) }
-        }
+    /// contained with the `MifcNorm` `struct`, normalized as `strategy` says.
+    pub fn into_normalized(self, strategy: &NormStrategy) -> Result<Mifc, MifcNormError> {
+        if self.mifc.exclude { return Err(MifcNormError::Excluded) }
         let value = self.mifc.value.ok_or(MifcNormError::NoValue)?;
-        let value_unit: SIUnit = self.mifc.value_unit
+        let value = Uncertain::new(value, self.mifc.uncertainty);
+        let value_unit: Unit = self.mifc.value_unit
             .as_ref()
             .ok_or(MifcNormError::NoValueUnit)?
             .parse()?;
         let info = self.normal_info.ok_or(MifcNormError::NoInfo)?;
 
         let sample_time = info.calc_sample_time();
-        let norm_val = to_ngday_millioncells(value, value_unit, &info);
+        let norm_val = normalize(value, value_unit, &info, strategy)?;
 
         let mut normalized_mifc = self.mifc;
-        let note = format!("Normalized from {v:.4} {vu} by a {s} {su} sample over {d} {ds} with an estimated {c} cells ", 
+        let basis = strategy.basis();
+        let mut note = format!("Normalized from {v:.4} {vu} by a {s} {su} sample",
             v = value, vu = value_unit,
             s = info.sample_volume, su = info.sample_vol_unit,
-            d = sample_time, ds = if sample_time > 1.0 {"days"} else {"day"},
-            c = info.cell_count
         );
+        if basis.per_day() {
+            note.push_str(&format!(" over {d} {ds}",
+                d = sample_time, ds = if sample_time > 1.0 {"days"} else {"day"}));
+        }
+        if let (true, Some(cells)) = (basis.per_cell(), info.cell_count) {
+            note.push_str(&format!(" with an estimated {:.0} cells", cells));
+        }
+        note.push(' ');
+        if let Some(mw) = info.mol_weight {
+            note.push_str(&format!("using a molecular weight of {} g/mol ", mw));
+        }
 
-        normalized_mifc.value = Some(norm_val);
-        normalized_mifc.value_unit = Some(format!("{}", SIUnit::ng_day_millioncells));        
+        normalized_mifc.value = Some(norm_val.value);
+        normalized_mifc.uncertainty = norm_val.uncertainty;
+        normalized_mifc.value_unit = Some(strategy.unit().to_string());
         normalized_mifc.notes = if let Some(mut n) = normalized_mifc.notes {
             if &n != "" { n.push_str(" || "); }
             n.push_str(&note);
@@ -71,45 +97,214 @@ impl MifcNorm {
 
         Ok(normalized_mifc)
     }
+
+    /// Mark this row as excluded if its Caution Flag has a code that `rules` excludes
+    pub fn apply_flag_rules(&mut self, rules: &ExclusionRules) {
+        self.mifc.apply_flag_rules(rules)
+    }
+
+    /// Fill in this row's Molecular Weight (g/mol) from `weights` when the row has no weight
+    /// of its own, and its Value Unit is molar while `strategy` normalizes into a mass (or the
+    /// other way around). Returns the weight that was filled in, if any.
+    pub fn fill_mol_weight(&mut self, weights: &MolecularWeights, strategy: &NormStrategy) -> Result<Option<f64>, MolWeightError> {
+        let info = match self.normal_info.as_mut() {
+            Some(info) if info.mol_weight.is_none() => info,
+            _ => return Ok(None),
+        };
+        let is_molar = match self.mifc.value_unit.as_ref().and_then(|u| u.parse::<Unit>().ok()) {
+            Some(u) if u.unit_type().is_mass_based() => u.unit_type() == UnitType::Molarity,
+            _ => return Ok(None),
+        };
+        if is_molar == strategy.is_molar() { return Ok(None) }
+
+        let target = &self.mifc.target;
+        let mw = weights.get(target)
+            .ok_or_else(|| MolWeightError::MissingTarget(target.clone()))?;
+        info.mol_weight = Some(mw);
+
+        Ok(Some(mw))
+    }
 }
 
 /// Necessary MIFC fields
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "MifcColumns", into = "MifcColumns")]
 pub struct Mifc {
-    #[serde(rename = "Chip ID")]
     pub id: String,
-    #[serde(rename = "Assay Plate ID")]
     pub assay_plate_id: Option<String>,
-    #[serde(rename = "Assay Well ID")]
     pub assay_well_id: Option<String>,
-    #[serde(rename = "Method/Kit")]
     pub method: String,
-    #[serde(rename = "Target/Analyte")]
     pub target: String,
-    #[serde(rename = "Subtarget")]
     pub subtarget: Option<String>,
-    #[serde(rename = "Sample Location")]
     pub sample_loc: String,
-    #[serde(rename = "Day")]
-    pub day: f64,
-    #[serde(rename = "Hour")]
-    pub hour: f64,
-    #[serde(rename = "Minute")]
-    pub min: f64,
-    #[serde(rename = "Value")]
+    /// Written as the Day, Hour, and Minute columns
+    pub time: MifcTime,
     pub value: Option<f64>,
-    #[serde(rename = "Value Unit")]
     pub value_unit: Option<String>, 
-    #[serde(rename = "Caution Flag")]
-    pub flag: Option<String>,
-    #[serde(rename = "Exclude")]
-    pub exclude: Option<String>,
-    #[serde(rename = "Notes")]
+    pub flag: Option<CautionFlag>,
+    /// If the row is excluded, from its Exclude cell or its Caution Flag.
+    /// Written as "X" in the Exclude column when `true`, unless the Exclude cell had other text.
+    pub exclude: bool,
+    /// The Exclude cell as it was entered, e.g. a reason for the exclusion
+    pub exclude_text: Option<String>,
     pub notes: Option<String>,
-    #[serde(rename = "Replicate")]
     pub replicate: Option<f32>,
-    #[serde(rename = "Cross Reference")]
     pub xref: Option<String>,
+    /// Absolute uncertainty (e.g., SD or SEM) of the Value, in the Value Unit
+    pub uncertainty: Option<f64>,
+    /// Cells of other columns from the input, which are written after the MIFC columns
+    pub extra: ExtraColumns,
+}
+
+/// The MIFC columns as they're written, with the time split into Day, Hour, and Minute
+#[derive(Debug, Serialize, Deserialize)]
+struct MifcColumns {
+    #[serde(rename = "Chip ID")]
+    id: String,
+    #[serde(rename = "Assay Plate ID", default, deserialize_with = "blank_as_none")]
+    assay_plate_id: Option<String>,
+    #[serde(rename = "Assay Well ID", default, deserialize_with = "blank_as_none")]
+    assay_well_id: Option<String>,
+    #[serde(rename = "Method/Kit")]
+    method: String,
+    #[serde(rename = "Target/Analyte")]
+    target: String,
+    #[serde(rename = "Subtarget", default, deserialize_with = "blank_as_none")]
+    subtarget: Option<String>,
+    #[serde(rename = "Sample Location")]
+    sample_loc: String,
+    #[serde(rename = "Day")]
+    day: f64,
+    #[serde(rename = "Hour")]
+    hour: f64,
+    #[serde(rename = "Minute")]
+    min: f64,
+    #[serde(rename = "Value", default, deserialize_with = "blank_as_none")]
+    value: Option<f64>,
+    #[serde(rename = "Value Unit", default, deserialize_with = "blank_as_none")]
+    value_unit: Option<String>, 
+    #[serde(rename = "Caution Flag", default, deserialize_with = "blank_as_none")]
+    flag: Option<CautionFlag>,
+    #[serde(rename = "Exclude", default, deserialize_with = "blank_as_none")]
+    exclude: Option<String>,
+    #[serde(rename = "Notes", default, deserialize_with = "blank_as_none")]
+    notes: Option<String>,
+    #[serde(rename = "Replicate", default, deserialize_with = "blank_as_none")]
+    replicate: Option<f32>,
+    #[serde(rename = "Cross Reference", default, deserialize_with = "blank_as_none")]
+    xref: Option<String>,
+    #[serde(rename = "Value Uncertainty", default, deserialize_with = "blank_as_none")]
+    uncertainty: Option<f64>,
+}
+
+impl From<MifcColumns> for Mifc {
+    fn from(c: MifcColumns) -> Self {
+        Mifc {
+            id: c.id,
+            assay_plate_id: c.assay_plate_id,
+            assay_well_id: c.assay_well_id,
+            method: c.method,
+            target: c.target,
+            subtarget: c.subtarget,
+            sample_loc: c.sample_loc,
+            time: MifcTime::from_dhm(c.day, c.hour, c.min),
+            value: c.value,
+            value_unit: c.value_unit,
+            flag: c.flag,
+            exclude: c.exclude.as_deref().is_some_and(exclude_mark),
+            exclude_text: c.exclude,
+            notes: c.notes,
+            replicate: c.replicate,
+            xref: c.xref,
+            uncertainty: c.uncertainty,
+            extra: ExtraColumns::new(),
+        }
+    }
+}
+
+impl From<Mifc> for MifcColumns {
+    fn from(m: Mifc) -> Self {
+        let (day, hour, min) = m.time.written_dhm();
+        let exclude = m.exclude_cell().map(str::to_string);
+
+        MifcColumns {
+            id: m.id,
+            assay_plate_id: m.assay_plate_id,
+            assay_well_id: m.assay_well_id,
+            method: m.method,
+            target: m.target,
+            subtarget: m.subtarget,
+            sample_loc: m.sample_loc,
+            day,
+            hour,
+            min,
+            value: m.value,
+            value_unit: m.value_unit,
+            flag: m.flag,
+            exclude,
+            notes: m.notes,
+            replicate: m.replicate,
+            xref: m.xref,
+            uncertainty: m.uncertainty,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Mifc {
+    /// A 12.5 ng/mL albumin row of chip C1 at day 1, for tests to change as needed
+    pub(crate) fn test_row() -> Mifc {
+        Mifc {
+            id: "C1".to_string(),
+            assay_plate_id: None,
+            assay_well_id: None,
+            method: "ELISA".to_string(),
+            target: "Albumin".to_string(),
+            subtarget: None,
+            sample_loc: "effluent".to_string(),
+            time: MifcTime::from_dhm(1.0, 0.0, 0.0),
+            value: Some(12.5),
+            value_unit: Some("ng/mL".to_string()),
+            flag: None,
+            exclude: false,
+            exclude_text: None,
+            notes: None,
+            replicate: None,
+            xref: None,
+            uncertainty: None,
+            extra: ExtraColumns::new(),
+        }
+    }
+}
+
+impl KeepExtra for Mifc {
+    fn layout() -> Layout {
+        Layout::Mifc
+    }
+
+    fn extra_mut(&mut self) -> &mut ExtraColumns {
+        &mut self.extra
+    }
+}
+
+impl KeepExtra for MifcNorm {
+    fn layout() -> Layout {
+        Layout::MifcNorm
+    }
+
+    fn extra_mut(&mut self) -> &mut ExtraColumns {
+        &mut self.mifc.extra
+    }
+}
+
+impl KeepExtra for MifcImage {
+    fn layout() -> Layout {
+        Layout::MifcImage
+    }
+
+    fn extra_mut(&mut self) -> &mut ExtraColumns {
+        &mut self.mifc.extra
+    }
 }
 
 /// MIFC Image Format
@@ -121,7 +316,7 @@ pub struct MifcImage {
     pub file: String,
     #[serde(rename = "Image Field")]
     pub field: u32,
-    #[serde(rename = "Image Field Description")]
+    #[serde(rename = "Image Field Description", default, deserialize_with = "blank_as_none")]
     pub field_desc: Option<String>,
     #[serde(rename = "Image Magnification")]
     pub magnification: f64,
@@ -131,45 +326,36 @@ pub struct MifcImage {
     pub resolution_unit: String,
     #[serde(rename = "Image Sample Label")]
     pub label: String,
-    #[serde(rename = "Image Sample Label Description")]
+    #[serde(rename = "Image Sample Label Description", default, deserialize_with = "blank_as_none")]
     pub label_desc: Option<String>,
-    #[serde(rename = "Image Wavelength (nm)")]
+    #[serde(rename = "Image Wavelength (nm)", default, deserialize_with = "blank_as_none")]
     pub wavelength: Option<String>,
     #[serde(rename = "Image Color Mapping")]
     pub colormap: String,
-    #[serde(rename = "Image Setting Note")]
+    #[serde(rename = "Image Setting Note", default, deserialize_with = "blank_as_none")]
     pub image_note: Option<String>,
 }
 
-impl MifcImage {
-    // TODO: Enum!
-    pub fn get_vocab_field(&self, field: &str) -> &str {
-        match field {
-            "Image File Name" => &self.file,
-            "Method/Kit" => &self.mifc.method,
-            "Target/Analyte" => &self.mifc.target,
-            "Sample Location" => &self.mifc.sample_loc,
-            "Value Unit" => self.mifc.value_unit.as_ref().map(|u| u.as_str()).unwrap_or(""),
-            "Chip ID" => &self.mifc.id,
-            _ => panic!("Not a vocab field"),
-        }
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Normalization {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Normalization {
     #[serde(rename = "Duration Sample Collection (days)")]
-    sample_days: f64,
+    pub(crate) sample_days: f64,
     #[serde(rename = "Duration Sample Collection (hours)")]
-    sample_hours: f64,
+    pub(crate) sample_hours: f64,
     #[serde(rename = "Duration Sample Collection (minutes)")]
-    sample_minutes: f64,
+    pub(crate) sample_minutes: f64,
     #[serde(rename = "Sample Volume")]
-    sample_volume: f64,
+    pub(crate) sample_volume: f64,
     #[serde(rename = "Sample Volume Unit")]
-    sample_vol_unit: SIUnit,
-    #[serde(rename = "Estimated Cell Number")]
-    cell_count: f64,
+    pub(crate) sample_vol_unit: SIUnit,
+    #[serde(rename = "Estimated Cell Number", default, deserialize_with = "blank_as_none")]
+    pub(crate) cell_count: Option<f64>,
+    #[serde(rename = "Molecular Weight (g/mol)", default, deserialize_with = "blank_as_none")]
+    pub(crate) mol_weight: Option<f64>,
+    #[serde(rename = "Sample Volume Uncertainty", default, deserialize_with = "blank_as_none")]
+    pub(crate) sample_volume_sd: Option<f64>,
+    #[serde(rename = "Estimated Cell Number Uncertainty", default, deserialize_with = "blank_as_none")]
+    pub(crate) cell_count_sd: Option<f64>,
 }
 
 impl Normalization {
@@ -182,25 +368,57 @@ impl Normalization {
     }
 }
 
-fn to_ngday_millioncells(val: f64, val_unit: SIUnit, norm: &Normalization) -> f64
+/// Normalize a concentration into the unit of `strategy`, propagating the uncertainty of the 
+/// value, sample volume, and cell count. The sample duration is taken as exact.
+fn normalize(val: Uncertain, val_unit: Unit, norm: &Normalization, strategy: &NormStrategy) -> Result<Uncertain, MifcNormError>
 {
     use self::SIUnit::*;
 
-    let &Normalization{cell_count: cells, sample_volume: vol, sample_vol_unit: vol_unit, ..} = norm;
+    let &Normalization{sample_vol_unit: vol_unit, mol_weight, ..} = norm;
+    let vol = Uncertain::new(norm.sample_volume, norm.sample_volume_sd);
+
+    let val_type = val_unit.unit_type();
+    if !val_type.is_mass_based() {
+        return Err(MifcNormError::NotMassBased(val_unit, val_type));
+    }
 
     let days = norm.calc_sample_time();
-    let si_val = units::convert((val, val_unit), g_l).unwrap();
-    let si_vol = units::convert((vol, vol_unit), l).unwrap();
-    trace!("conc: {:.5} {} to SI {:.5} {}", val, val_unit, si_val, g_l);
+    // molar concentrations need a molecular weight to become a mass concentration,
+    // and mass concentrations need one to become a molar concentration
+    let si_conc: Unit = if strategy.is_molar() {
+        "mol/L".parse().expect("mol/L is a valid unit")
+    } else {
+        g_l.into()
+    };
+    let si_val = match mol_weight {
+        Some(mw) => units::convert_molar((val, val_unit), si_conc, mw),
+        None => units::convert((val, val_unit), si_conc),
+    }
+    .map_err(|e| match e {
+        SIError::IncompatibleTypes(UnitType::Molarity, _) => MifcNormError::NoMolecularWeight,
+        SIError::IncompatibleTypes(_, UnitType::Molarity) => MifcNormError::NoMolecularWeightForMolar,
+        e => MifcNormError::UnitConversion(e),
+    })?;
+    let si_vol = units::convert((vol, vol_unit), l)
+        .map_err(MifcNormError::UnitConversion)?;
+    trace!("conc: {:.5} {} to SI {:.5} {}", val, val_unit, si_val, si_conc);
     trace!("vol: {:.5} {} to SI {:.5} {}",  vol, vol_unit, si_vol, l);
 
-    // first go from the concentration (g/L) and sample volume (L) 
-    // into nanograms/day/cell
-    let made_ng = units::convert((si_val * si_vol, g), ng).unwrap();
-    trace!("produced ng: {:.5} over {:.3} day(s)", made_ng, days);
-    let ngdaycell = made_ng / days / cells;
-    // now, multiple by 10^6 to make rate by million cells 
-    ngdaycell * 1_000_000.0
+    // first go from the concentration (g/L or mol/L) and sample volume (L) into
+    // grams or moles, then divide by the days and cells of the strategy
+    let basis = strategy.basis();
+    let mut made = si_val * si_vol;
+    trace!("produced {:.5} {} over {:.3} day(s)", made, if strategy.is_molar() {"mol"} else {"g"}, days);
+    if basis.per_day() {
+        made = made * days.recip();
+    }
+    if basis.per_cell() {
+        let cells = norm.cell_count.ok_or(MifcNormError::NoCellCount)?;
+        made = made / Uncertain::new(cells, norm.cell_count_sd);
+    }
+    // now, put the rate into the output unit, e.g. ng/day/10^6 cells
+    units::convert((made, strategy.si_unit()), strategy.unit())
+        .map_err(MifcNormError::UnitConversion)
 }
 
 #[cfg(test)]
@@ -208,6 +426,7 @@ mod tests {
     use super::*;
     use test_utils::double_comparable;
     use units::SIUnit::*;
+    use crate::norm::NormBasis;
 
     struct Norm {
         val: f64,
@@ -225,7 +444,10 @@ mod tests {
                 sample_minutes: 0.0,
                 sample_volume: 200.0,
                 sample_vol_unit: ul,
-                cell_count: 16768.0,
+                cell_count: Some(16768.0),
+                mol_weight: None,
+                sample_volume_sd: None,
+                cell_count_sd: None,
             }
         },
         Norm {
@@ -237,7 +459,10 @@ mod tests {
                 sample_minutes: 0.0,
                 sample_volume: 200.0,
                 sample_vol_unit: ul,
-                cell_count: 16768.0,
+                cell_count: Some(16768.0),
+                mol_weight: None,
+                sample_volume_sd: None,
+                cell_count_sd: None,
             }
         },
         Norm {
@@ -249,7 +474,10 @@ mod tests {
                 sample_minutes: 0.0,
                 sample_volume: 300.0,
                 sample_vol_unit: ul,
-                cell_count: 80000.0,
+                cell_count: Some(80000.0),
+                mol_weight: None,
+                sample_volume_sd: None,
+                cell_count_sd: None,
             }
         },
         Norm {
@@ -261,7 +489,10 @@ mod tests {
                 sample_minutes: 0.0,
                 sample_volume: 200.0,
                 sample_vol_unit: ul,
-                cell_count: 50000.0,
+                cell_count: Some(50000.0),
+                mol_weight: None,
+                sample_volume_sd: None,
+                cell_count_sd: None,
             }
         },
         Norm {
@@ -273,7 +504,10 @@ mod tests {
                 sample_minutes: 0.0,
                 sample_volume: 500.0,
                 sample_vol_unit: ul,
-                cell_count: 50000.0,
+                cell_count: Some(50000.0),
+                mol_weight: None,
+                sample_volume_sd: None,
+                cell_count_sd: None,
             }
         },
         Norm {
@@ -285,7 +519,10 @@ mod tests {
                 sample_minutes: 2.0,
                 sample_volume: 100.0,
                 sample_vol_unit: ul,
-                cell_count: 50000.0,
+                cell_count: Some(50000.0),
+                mol_weight: None,
+                sample_volume_sd: None,
+                cell_count_sd: None,
             }
         },
         Norm {
@@ -297,7 +534,10 @@ mod tests {
                 sample_minutes: 30.0,
                 sample_volume: 0.1,
                 sample_vol_unit: ml,
-                cell_count: 50000.0,
+                cell_count: Some(50000.0),
+                mol_weight: None,
+                sample_volume_sd: None,
+                cell_count_sd: None,
             }
         },
         Norm {
@@ -309,7 +549,10 @@ mod tests {
                 sample_minutes: 1.0,
                 sample_volume: 200.0,
                 sample_vol_unit: ul,
-                cell_count: 20000.0,
+                cell_count: Some(20000.0),
+                mol_weight: None,
+                sample_volume_sd: None,
+                cell_count_sd: None,
             }
         },
         Norm {
@@ -321,7 +564,10 @@ mod tests {
                 sample_minutes: 0.0,
                 sample_volume: 0.1,
                 sample_vol_unit: ml,
-                cell_count: 20000.0,
+                cell_count: Some(20000.0),
+                mol_weight: None,
+                sample_volume_sd: None,
+                cell_count_sd: None,
             }
         },
         Norm {
@@ -333,7 +579,10 @@ mod tests {
                 sample_minutes: 30.0,
                 sample_volume: 0.01,
                 sample_vol_unit: l,
-                cell_count: 20000.0,
+                cell_count: Some(20000.0),
+                mol_weight: None,
+                sample_volume_sd: None,
+                cell_count_sd: None,
             }
         },
     ];
@@ -356,7 +605,7 @@ mod tests {
         const PERCENT_TOLERANCE: f64 = 0.001;
 
         let all_equal = INPUTS.iter()
-            .map(|i| to_ngday_millioncells(i.val, i.val_unit, &i.info))
+            .map(|i| normalize(Uncertain::exact(i.val), i.val_unit.into(), &i.info, &NormStrategy::default()).unwrap().value)
             .zip(OUTPUTS.iter())
             .enumerate()
             .inspect(|(i, (c, e))|
@@ -368,4 +617,191 @@ mod tests {
 
         assert!(all_equal);
     }
+
+    /// 500 ng/mL in a 200 µL sample over 2 days from 20,000 cells, which is 100 ng
+    fn two_day_info() -> Normalization {
+        Normalization {
+            sample_days: 2.0,
+            sample_hours: 0.0,
+            sample_minutes: 0.0,
+            sample_volume: 200.0,
+            sample_vol_unit: ul,
+            cell_count: Some(20000.0),
+            mol_weight: None,
+            sample_volume_sd: None,
+            cell_count_sd: None,
+        }
+    }
+
+    fn normalize_by(basis: NormBasis, unit: Option<&str>) -> f64 {
+        let mut strategy = NormStrategy::new(basis);
+        if let Some(u) = unit {
+            strategy = strategy.with_unit(u.parse().unwrap()).unwrap();
+        }
+        normalize(Uncertain::exact(500.0), ng_ml.into(), &two_day_info(), &strategy).unwrap().value
+    }
+
+    #[test]
+    fn mass_normalization() {
+        const PERCENT_TOLERANCE: f64 = 0.001;
+        let mass = normalize_by(NormBasis::Mass, None);
+        assert!(double_comparable(mass, 100.0, PERCENT_TOLERANCE), "calculated: {}", mass);
+        let mass = normalize_by(NormBasis::Mass, Some("µg"));
+        assert!(double_comparable(mass, 0.1, PERCENT_TOLERANCE), "calculated: {}", mass);
+    }
+
+    #[test]
+    fn ng_day_normalization() {
+        const PERCENT_TOLERANCE: f64 = 0.001;
+        let rate = normalize_by(NormBasis::Rate, None);
+        assert!(double_comparable(rate, 50.0, PERCENT_TOLERANCE), "calculated: {}", rate);
+        let rate = normalize_by(NormBasis::Rate, Some("pg/hour"));
+        assert!(double_comparable(rate, 50_000.0 / 24.0, PERCENT_TOLERANCE), "calculated: {}", rate);
+    }
+
+    #[test]
+    fn per_cell_normalization() {
+        const PERCENT_TOLERANCE: f64 = 0.001;
+        let per_cell = normalize_by(NormBasis::PerCell, None);
+        assert!(double_comparable(per_cell, 5_000.0, PERCENT_TOLERANCE), "calculated: {}", per_cell);
+        let per_cell = normalize_by(NormBasis::PerCell, Some("pg/cell"));
+        assert!(double_comparable(per_cell, 5.0, PERCENT_TOLERANCE), "calculated: {}", per_cell);
+    }
+
+    #[test]
+    fn chosen_unit_normalization() {
+        const PERCENT_TOLERANCE: f64 = 0.001;
+        let cases = [
+            (None, 2_500.0),
+            (Some("pg/day/10^3 cells"), 2_500.0),
+            (Some("µg/day/10^6 cells"), 2.5),
+            (Some("pg/day/10^6 cells"), 2_500_000.0),
+        ];
+        for &(unit, expected) in cases.iter() {
+            let rate = normalize_by(NormBasis::CellRate, unit);
+            assert!(double_comparable(rate, expected, PERCENT_TOLERANCE), "{:?} calculated: {}", unit, rate);
+        }
+    }
+
+    #[test]
+    fn molar_rate_normalization() {
+        const PERCENT_TOLERANCE: f64 = 0.001;
+        // 1 µmol/L in 200 µL over 2 days from 20,000 cells is 100 pmol/day
+        let strategy = NormStrategy::molar(NormBasis::CellRate);
+        for &(val, unit) in [(1.0, "µM"), (1000.0, "nM"), (0.001, "mM")].iter() {
+            let rate = normalize(Uncertain::exact(val), unit.parse().unwrap(), &two_day_info(), &strategy).unwrap().value;
+            assert!(double_comparable(rate, 5_000.0, PERCENT_TOLERANCE), "{} {} calculated: {}", val, unit, rate);
+        }
+
+        let by_weight = Normalization { mol_weight: Some(500.0), ..two_day_info() };
+        let rate = normalize(Uncertain::exact(500.0), ng_ml.into(), &by_weight, &strategy).unwrap().value;
+        assert!(double_comparable(rate, 5_000.0, PERCENT_TOLERANCE), "calculated: {}", rate);
+
+        match normalize(Uncertain::exact(500.0), ng_ml.into(), &two_day_info(), &strategy) {
+            Err(MifcNormError::NoMolecularWeightForMolar) => (),
+            other => panic!("expected a missing molecular weight error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn molar_strategy_fills_mass_row_weights() {
+        const PERCENT_TOLERANCE: f64 = 0.001;
+        let input = "Chip ID,Method/Kit,Target/Analyte,Sample Location,Day,Hour,Minute,Value,Value Unit,\
+                     Duration Sample Collection (days),Duration Sample Collection (hours),\
+                     Duration Sample Collection (minutes),Sample Volume,Sample Volume Unit,Estimated Cell Number\n\
+                     C1,LC-MS,Caffeine,effluent,2,0,0,500,ng/mL,2,0,0,200,µL,20000\n";
+        let read = || -> MifcNorm {
+            csv::Reader::from_reader(input.as_bytes()).deserialize().next().unwrap().unwrap()
+        };
+        let mut weights = MolecularWeights::new();
+        weights.insert("caffeine", 500.0, None).unwrap();
+
+        let mut mass = read();
+        assert_eq!(mass.fill_mol_weight(&weights, &NormStrategy::default()).unwrap(), None);
+
+        let strategy = NormStrategy::molar(NormBasis::CellRate);
+        let mut molar = read();
+        assert_eq!(molar.fill_mol_weight(&weights, &strategy).unwrap(), Some(500.0));
+        let normalized = molar.into_normalized(&strategy).unwrap();
+        assert!(double_comparable(normalized.value.unwrap(), 5_000.0, PERCENT_TOLERANCE));
+        assert_eq!(normalized.value_unit.as_deref(), Some("pmol/day/10^6 cells"));
+    }
+
+    #[test]
+    fn time_is_read_and_written_as_columns() {
+        let input = "Chip ID,Method/Kit,Target/Analyte,Sample Location,Day,Hour,Minute,Value\n\
+                     C1,ELISA,Albumin,effluent,0,36,0,1.5\n";
+        let mut rdr = csv::Reader::from_reader(input.as_bytes());
+        let row: Mifc = rdr.deserialize().next().unwrap().unwrap();
+        assert_eq!(row.time, MifcTime::from_dhm(1.0, 12.0, 0.0));
+
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        wtr.serialize(&row).unwrap();
+        let output = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        let mut lines = output.lines();
+
+        assert!(lines.next().unwrap().contains(",Sample Location,Day,Hour,Minute,Value,"));
+        // the parts are written as they were entered
+        assert!(lines.next().unwrap().starts_with("C1,,,ELISA,Albumin,,effluent,0,36,0,1.5,"));
+
+        // a time that wasn't entered as parts is written in canonical parts
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        wtr.serialize(&Mifc { time: MifcTime::from_minutes(36.0 * 60.0), ..row }).unwrap();
+        let output = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert!(output.lines().nth(1).unwrap().starts_with("C1,,,ELISA,Albumin,,effluent,1,12,0,1.5,"));
+    }
+
+    #[test]
+    fn molar_normalization() {
+        const PERCENT_TOLERANCE: f64 = 0.001;
+        // 1 umol/L of a 500 g/mol compound is 500 ng/mL
+        let info = Normalization { sample_days: 1.0, mol_weight: Some(500.0), ..two_day_info() };
+        let molar = normalize(Uncertain::exact(1.0), umol_l.into(), &info, &NormStrategy::default()).unwrap().value;
+        let mass = normalize(Uncertain::exact(500.0), ng_ml.into(), &info, &NormStrategy::default()).unwrap().value;
+
+        assert!(double_comparable(molar, 5_000.0, PERCENT_TOLERANCE), "calculated: {}", molar);
+        assert!(double_comparable(molar, mass, PERCENT_TOLERANCE));
+    }
+
+    #[test]
+    fn uncertainty_propagation() {
+        const PERCENT_TOLERANCE: f64 = 0.001;
+        // 10% uncertainty in each of the value, volume, and cell count
+        let info = Normalization {
+            sample_days: 1.0,
+            sample_volume_sd: Some(20.0),
+            cell_count_sd: Some(2000.0),
+            ..two_day_info()
+        };
+        let norm = normalize(Uncertain::new(500.0, Some(50.0)), ng_ml.into(), &info, &NormStrategy::default()).unwrap();
+
+        assert!(double_comparable(norm.value, 5_000.0, PERCENT_TOLERANCE), "calculated: {}", norm);
+        assert!(double_comparable(norm.uncertainty.unwrap(), 5_000.0 * 0.1 * 3f64.sqrt(), PERCENT_TOLERANCE), "calculated: {}", norm);
+
+        let exact = Normalization { sample_volume_sd: None, cell_count_sd: None, ..info };
+        let norm = normalize(Uncertain::exact(500.0), ng_ml.into(), &exact, &NormStrategy::default()).unwrap();
+        assert_eq!(norm.uncertainty, None);
+    }
+
+    #[test]
+    fn molar_normalization_without_weight() {
+        let info = two_day_info();
+
+        match normalize(Uncertain::exact(1.0), umol_l.into(), &info, &NormStrategy::default()) {
+            Err(MifcNormError::NoMolecularWeight) => (),
+            other => panic!("expected a missing molecular weight error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn non_mass_units_are_not_normalized() {
+        let info = Normalization { mol_weight: Some(500.0), ..two_day_info() };
+
+        for &unit in [u_l, cells_ml, percent, fold_change, ohm_cm2].iter() {
+            match normalize(Uncertain::exact(1.0), unit.into(), &info, &NormStrategy::default()) {
+                Err(MifcNormError::NotMassBased(..)) => (),
+                other => panic!("expected {} to not be normalizable, got {:?}", unit, other),
+            }
+        }
+    }
 }
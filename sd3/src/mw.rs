@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use serde_derive::Deserialize;
+use failure::{Fail};
+use units::{SIError, Uncertain, Unit, UnitType, self};
+use crate::mifc::Mifc;
+use crate::lookup::key;
+
+#[derive(Debug, Fail)]
+/// Errors that can occur loading a molecular weight registry or converting with it
+pub enum MolWeightError {
+    #[fail(display = "couldn't read molecular weight file <{}>", _0)]
+    Io(String, #[cause] std::io::Error),
+    #[fail(display = "couldn't parse TOML molecular weight file <{}>", _0)]
+    Toml(String, #[cause] toml::de::Error),
+    #[fail(display = "couldn't parse CSV molecular weight file <{}>", _0)]
+    Csv(String, #[cause] csv::Error),
+    #[fail(display = "molecular weight file <{}> was not a .toml or .csv file", _0)]
+    UnkFormat(String),
+    #[fail(display = "target <{}> had an invalid molecular weight <{}>", _0, _1)]
+    InvalidWeight(String, f64),
+    #[fail(display = "target <{}> is not in the molecular weight registry", _0)]
+    MissingTarget(String),
+    #[fail(display = "couldn't convert the row's Value")]
+    Unit(#[cause] SIError),
+}
+
+/// A row of a CSV molecular weight file
+#[derive(Debug, Deserialize)]
+struct CsvEntry {
+    #[serde(rename = "Target/Analyte")]
+    target: String,
+    #[serde(rename = "Molecular Weight (g/mol)")]
+    mw: f64,
+    /// Other names for the target, separated by semicolons
+    #[serde(rename = "Aliases")]
+    aliases: Option<String>,
+}
+
+/// A TOML molecular weight entry: either a bare weight, or a weight with aliases
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TomlEntry {
+    Weight(f64),
+    WithAliases {
+        mw: f64,
+        #[serde(default)]
+        aliases: Vec<String>,
+    },
+}
+
+/// A lookup of Target/Analyte name to molecular weight (g/mol). Names are
+/// matched ignoring case and surrounding whitespace.
+#[derive(Debug, Default)]
+pub struct MolecularWeights {
+    weights: HashMap<String, f64>,
+}
+
+impl MolecularWeights {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a registry from a `.csv` file with `Target/Analyte`, `Molecular Weight (g/mol)`,
+    /// and optional `Aliases` (separated by `;`) columns, or a `.toml` file of
+    /// `Target = mw` or `Target = { mw = 194.19, aliases = ["..."] }` entries
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, MolWeightError> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        let mut registry = Self::new();
+
+        match ext.as_deref() {
+            Some("csv") => {
+                let mut rdr = csv::Reader::from_path(path)
+                    .map_err(|e| MolWeightError::Csv(name.clone(), e))?;
+                for entry in rdr.deserialize() {
+                    let CsvEntry { target, mw, aliases } = entry
+                        .map_err(|e| MolWeightError::Csv(name.clone(), e))?;
+                    let aliases = aliases.iter()
+                        .flat_map(|a| a.split(';'))
+                        .filter(|a| !a.trim().is_empty());
+                    registry.insert(&target, mw, aliases)?;
+                }
+            },
+            Some("toml") => {
+                let contents = fs::read_to_string(path)
+                    .map_err(|e| MolWeightError::Io(name.clone(), e))?;
+                let table: HashMap<String, TomlEntry> = toml::from_str(&contents)
+                    .map_err(|e| MolWeightError::Toml(name.clone(), e))?;
+                for (target, entry) in table {
+                    match entry {
+                        TomlEntry::Weight(mw) => registry.insert(&target, mw, None::<&str>)?,
+                        TomlEntry::WithAliases { mw, aliases } =>
+                            registry.insert(&target, mw, aliases.iter().map(String::as_str))?,
+                    }
+                }
+            },
+            _ => return Err(MolWeightError::UnkFormat(name)),
+        }
+
+        Ok(registry)
+    }
+
+    /// Add a target, and any other names for it, with molecular weight `mw` (g/mol)
+    pub fn insert<'a, A>(&mut self, target: &str, mw: f64, aliases: A) -> Result<(), MolWeightError>
+    where A: IntoIterator<Item = &'a str>
+    {
+        if !mw.is_finite() || mw <= 0.0 {
+            return Err(MolWeightError::InvalidWeight(target.to_string(), mw));
+        }
+
+        self.weights.insert(key(target), mw);
+        for alias in aliases {
+            self.weights.insert(key(alias), mw);
+        }
+
+        Ok(())
+    }
+
+    /// Get the molecular weight (g/mol) for a target
+    pub fn get(&self, target: &str) -> Option<f64> {
+        self.weights.get(&key(target)).cloned()
+    }
+
+    /// The number of names (targets and aliases) in the registry
+    pub fn len(&self) -> usize {
+        self.weights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.weights.is_empty()
+    }
+}
+
+impl Mifc {
+    /// Convert this row's Value into unit `to`. Going between molar and mass
+    /// concentrations uses this row's Target/Analyte molecular weight from `weights`.
+    /// Rows without a Value or Value Unit are left unchanged.
+    pub fn convert_value(&mut self, to: Unit, weights: &MolecularWeights) -> Result<(), MolWeightError> {
+        use self::UnitType::*;
+
+        let (value, from) = match (self.value, self.value_unit.as_ref()) {
+            (Some(v), Some(u)) => (Uncertain::new(v, self.uncertainty), u.parse::<Unit>().map_err(MolWeightError::Unit)?),
+            _ => return Ok(()),
+        };
+
+        let (converted, mw) = match units::convert((value, from), to) {
+            Ok(v) => (v, None),
+            Err(SIError::IncompatibleTypes(Molarity, Concentration))
+            | Err(SIError::IncompatibleTypes(Concentration, Molarity)) => {
+                let mw = weights.get(&self.target)
+                    .ok_or_else(|| MolWeightError::MissingTarget(self.target.clone()))?;
+                let v = units::convert_molar((value, from), to, mw)
+                    .map_err(MolWeightError::Unit)?;
+                (v, Some(mw))
+            },
+            Err(e) => return Err(MolWeightError::Unit(e)),
+        };
+
+        self.value = Some(converted.value);
+        self.uncertainty = converted.uncertainty;
+        self.value_unit = Some(to.to_string());
+        if let Some(mw) = mw {
+            let note = format!("Converted from {} {} using a molecular weight of {} g/mol", value, from, mw);
+            self.notes = match self.notes.take() {
+                Some(mut n) if !n.is_empty() => { n.push_str(" || "); n.push_str(&note); Some(n) },
+                _ => Some(note),
+            };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::double_comparable;
+
+    fn caffeine(value: f64, unit: &str) -> Mifc {
+        Mifc {
+            method: "LC-MS/MS".to_string(),
+            target: "Caffeine".to_string(),
+            value: Some(value),
+            value_unit: Some(unit.to_string()),
+            uncertainty: Some(0.1),
+            ..Mifc::test_row()
+        }
+    }
+
+    #[test]
+    fn lookup_ignores_case_and_aliases() {
+        let mut mws = MolecularWeights::new();
+        mws.insert("Caffeine", 194.19, vec!["1,3,7-Trimethylxanthine"]).unwrap();
+
+        assert_eq!(mws.get(" caffeine "), Some(194.19));
+        assert_eq!(mws.get("1,3,7-trimethylxanthine"), Some(194.19));
+        assert_eq!(mws.get("Vincristine"), None);
+        assert!(mws.insert("Bad", -1.0, None::<&str>).is_err());
+    }
+
+    #[test]
+    fn convert_molar_rows() {
+        let mut mws = MolecularWeights::new();
+        mws.insert("caffeine", 194.19, None::<&str>).unwrap();
+        let ng_ml: Unit = "ng/mL".parse().unwrap();
+        let um: Unit = "µM".parse().unwrap();
+
+        let mut row = caffeine(1.0, "uM");
+        row.convert_value(ng_ml, &mws).unwrap();
+        assert!(double_comparable(row.value.unwrap(), 194.19, 1e-9));
+        assert!(double_comparable(row.uncertainty.unwrap(), 19.419, 1e-9));
+        assert_eq!(row.value_unit.as_deref(), Some("ng/mL"));
+        assert!(row.notes.is_some());
+
+        row.convert_value(um, &mws).unwrap();
+        assert!(double_comparable(row.value.unwrap(), 1.0, 1e-9));
+
+        let mut missing = caffeine(1.0, "uM");
+        missing.target = "Vincristine".to_string();
+        match missing.convert_value(ng_ml, &mws) {
+            Err(MolWeightError::MissingTarget(t)) => assert_eq!(t, "Vincristine"),
+            other => panic!("expected a missing target, got {:?}", other),
+        }
+    }
+}
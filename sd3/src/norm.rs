@@ -0,0 +1,186 @@
+//! Strategies for normalizing the Value of a `MifcNorm` row: what the mass (or molar
+//! amount) in a sample is divided by, and the unit that the result is written in
+use std::fmt;
+use std::str::FromStr;
+use failure::{Fail};
+use units::{Dimension, SIUnit, Unit};
+
+#[derive(Debug, Fail)]
+pub enum NormStrategyError {
+    #[fail(display = "<{}> is a {}, but {} normalization needs a unit of {} (or {})", unit, found, basis, mass, molar)]
+    WrongUnit { unit: Unit, found: Dimension, basis: NormBasis, mass: Dimension, molar: Dimension },
+}
+
+/// What the mass of a target in a sample is divided by
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NormBasis {
+    /// Mass per day per cell, e.g. ng/day/10^6 cells
+    CellRate,
+    /// Mass per day, e.g. ng/day
+    Rate,
+    /// The total mass in the sample volume, e.g. ng
+    Mass,
+    /// Mass per cell, without the sample duration, e.g. ng/10^6 cells
+    PerCell,
+}
+
+impl NormBasis {
+    pub fn all() -> impl Iterator<Item = NormBasis> {
+        use self::NormBasis::*;
+        [CellRate, Rate, Mass, PerCell].iter().cloned()
+    }
+
+    /// The spelling used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            NormBasis::CellRate => "cell-rate",
+            NormBasis::Rate => "rate",
+            NormBasis::Mass => "mass",
+            NormBasis::PerCell => "per-cell",
+        }
+    }
+
+    /// The unit results are written in, unless another is chosen
+    pub fn default_unit(&self) -> Unit {
+        match self {
+            NormBasis::CellRate => SIUnit::ng_day_millioncells.unit(),
+            NormBasis::Rate => SIUnit::ng_day.unit(),
+            NormBasis::Mass => SIUnit::ng.unit(),
+            NormBasis::PerCell => "ng/10^6 cells".parse().expect("ng/10^6 cells is a valid unit"),
+        }
+    }
+
+    /// The unit molar results are written in, unless another is chosen
+    pub fn default_molar_unit(&self) -> Unit {
+        let unit = match self {
+            NormBasis::CellRate => "pmol/day/10^6 cells",
+            NormBasis::Rate => "pmol/day",
+            NormBasis::Mass => "pmol",
+            NormBasis::PerCell => "pmol/10^6 cells",
+        };
+        unit.parse().expect("default molar units are valid units")
+    }
+
+    /// Check if the mass is divided by the sample duration
+    pub fn per_day(&self) -> bool {
+        matches!(self, NormBasis::CellRate | NormBasis::Rate)
+    }
+
+    /// Check if the mass is divided by the estimated cell number
+    pub fn per_cell(&self) -> bool {
+        matches!(self, NormBasis::CellRate | NormBasis::PerCell)
+    }
+
+    /// The unit of a mass in grams, or an amount in moles, after it's divided by days and cells
+    pub(crate) fn si_unit(&self, molar: bool) -> Unit {
+        let mut unit = if molar { "mol" } else { "g" }.to_string();
+        if self.per_day() { unit.push_str("/day"); }
+        if self.per_cell() { unit.push_str("/cell"); }
+
+        unit.parse().expect("SI normalization units are valid units")
+    }
+}
+
+impl fmt::Display for NormBasis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for NormBasis {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        NormBasis::all()
+            .find(|b| b.name() == s)
+            .ok_or_else(|| format!("unknown normalization <{}>; expected cell-rate, rate, mass, or per-cell", s))
+    }
+}
+
+/// How `MifcNorm` rows are normalized: the basis, and the unit of the normalized Value.
+/// By default, values are normalized into ng/day/10^6 cells. A molar unit, like
+/// pmol/day/10^6 cells, normalizes values into an amount of the target instead of a mass.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NormStrategy {
+    basis: NormBasis,
+    unit: Unit,
+}
+
+impl NormStrategy {
+    /// Normalize by `basis`, writing results in its default unit
+    pub fn new(basis: NormBasis) -> Self {
+        NormStrategy { basis, unit: basis.default_unit() }
+    }
+
+    /// Normalize by `basis` into an amount of the target, written in its default molar unit
+    pub fn molar(basis: NormBasis) -> Self {
+        NormStrategy { basis, unit: basis.default_molar_unit() }
+    }
+
+    /// Write results in `unit`, which has to measure the same kind of quantity as the basis,
+    /// e.g. pg/day/10^3 cells, µg/day/10^6 cells, or pmol/day/10^6 cells for `NormBasis::CellRate`
+    pub fn with_unit(self, unit: Unit) -> Result<Self, NormStrategyError> {
+        let mass = self.basis.si_unit(false).dimension();
+        let molar = self.basis.si_unit(true).dimension();
+        if unit.dimension() != mass && unit.dimension() != molar {
+            return Err(NormStrategyError::WrongUnit {
+                unit,
+                found: unit.dimension(),
+                basis: self.basis,
+                mass,
+                molar,
+            });
+        }
+
+        Ok(NormStrategy { unit, ..self })
+    }
+
+    /// Check if values are normalized into an amount (in moles) instead of a mass
+    pub fn is_molar(&self) -> bool {
+        self.unit.dimension().amount != 0
+    }
+
+    /// The unit of a normalized value before it's put into the chosen unit
+    pub(crate) fn si_unit(&self) -> Unit {
+        self.basis.si_unit(self.is_molar())
+    }
+
+    pub fn basis(&self) -> NormBasis {
+        self.basis
+    }
+
+    pub fn unit(&self) -> Unit {
+        self.unit
+    }
+}
+
+impl Default for NormStrategy {
+    fn default() -> Self {
+        NormStrategy::new(NormBasis::CellRate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strategy_units() {
+        for basis in NormBasis::all() {
+            assert_eq!(basis.name().parse::<NormBasis>(), Ok(basis));
+            assert!(NormStrategy::new(basis).with_unit(basis.default_unit()).is_ok());
+            assert!(NormStrategy::new(basis).with_unit(basis.default_molar_unit()).unwrap().is_molar());
+            assert!(!NormStrategy::new(basis).is_molar());
+        }
+        assert!("volume".parse::<NormBasis>().is_err());
+
+        let pg: Unit = "pg/day/10^3 cells".parse().unwrap();
+        assert_eq!(NormStrategy::default().with_unit(pg).unwrap().unit(), pg);
+        match NormStrategy::new(NormBasis::Rate).with_unit(pg) {
+            Err(e) => assert_eq!(e.to_string(),
+                "<pg/day/10^3 cells> is a mass/time/cells, but rate normalization needs a unit of mass/time (or amount/time)"),
+            Ok(s) => panic!("{} shouldn't be a unit for rate normalization, got {:?}", pg, s),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+use failure::{Fail};
+
+const MINS_PER_HOUR: f64 = 60.0;
+const MINS_PER_DAY: f64 = 24.0 * MINS_PER_HOUR;
+/// Canonical parts are rounded to this many decimal places of a minute, to hide float error
+const MIN_DECIMALS: i32 = 6;
+
+#[derive(Debug, Fail)]
+#[fail(display = "couldn't read <{}> as a time; expected d.h.m (e.g., 1.12.0), units (e.g., 36h or 1d 12h), or ISO-8601 (e.g., P1DT12H)", _0)]
+pub struct TimeParseError(String);
+
+/// A MIFC timepoint (or duration), stored as a total number of minutes so that
+/// times can be compared and added no matter how their days, hours, and minutes were split.
+/// A time made from days, hours, and minutes also remembers those parts, so they can be validated.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MifcTime {
+    minutes: f64,
+    entered: Option<(f64, f64, f64)>,
+}
+
+impl MifcTime {
+    pub fn from_dhm(day: f64, hour: f64, min: f64) -> Self {
+        MifcTime {
+            minutes: day * MINS_PER_DAY + hour * MINS_PER_HOUR + min,
+            entered: Some((day, hour, min)),
+        }
+    }
+
+    pub fn from_minutes(minutes: f64) -> Self {
+        MifcTime { minutes, entered: None }
+    }
+
+    pub fn total_minutes(&self) -> f64 {
+        self.minutes
+    }
+
+    pub fn total_hours(&self) -> f64 {
+        self.minutes / MINS_PER_HOUR
+    }
+
+    pub fn total_days(&self) -> f64 {
+        self.minutes / MINS_PER_DAY
+    }
+
+    /// Split into canonical whole days, whole hours (< 24), and minutes (< 60),
+    /// e.g. 0 days and 36 hours becomes 1 day and 12 hours. Negative times have
+    /// every part negative. Minutes are rounded to a millionth of a minute, so a
+    /// time like 1.5 days doesn't come out as 59.99999 minutes.
+    pub fn dhm(&self) -> (f64, f64, f64) {
+        let minutes = round_minutes(self.minutes);
+        let day = (minutes / MINS_PER_DAY).trunc();
+        let rest = minutes - day * MINS_PER_DAY;
+        let hour = (rest / MINS_PER_HOUR).trunc();
+        let min = round_minutes(rest - hour * MINS_PER_HOUR);
+
+        (day, hour, min)
+    }
+
+    /// The parts to write out: the days, hours, and minutes as they were entered,
+    /// or the canonical parts of a time that wasn't made from them
+    pub fn written_dhm(&self) -> (f64, f64, f64) {
+        self.entered.unwrap_or_else(|| self.dhm())
+    }
+
+    /// The days, hours, and minutes as they were entered, if this time was made
+    /// from them, before being split into canonical parts
+    pub fn entered_dhm(&self) -> Option<(f64, f64, f64)> {
+        self.entered
+    }
+}
+
+impl PartialEq for MifcTime {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MifcTime {}
+
+impl PartialOrd for MifcTime {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MifcTime {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.minutes.total_cmp(&other.minutes)
+    }
+}
+
+impl Add for MifcTime {
+    type Output = Self;
+
+    fn add(self, o: Self) -> Self {
+        MifcTime::from_minutes(self.minutes + o.minutes)
+    }
+}
+
+impl Sub for MifcTime {
+    type Output = Self;
+
+    fn sub(self, o: Self) -> Self {
+        MifcTime::from_minutes(self.minutes - o.minutes)
+    }
+}
+
+/// Written as canonical d.h.m
+impl fmt::Display for MifcTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (d, h, m) = self.dhm();
+        write!(f, "{}.{}.{}", d, h, m)
+    }
+}
+
+impl FromStr for MifcTime {
+    type Err = TimeParseError;
+
+    /// Parse "d.h.m" (where hours and minutes are optional, so "2" is 2 days),
+    /// numbers with units like "36h", "90 min", or "1d 12h", and ISO-8601
+    /// durations like "P1DT12H" or "PT36H"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let time = s.trim();
+        let iso = time.strip_prefix('P').or_else(|| time.strip_prefix('p'));
+
+        match iso {
+            Some(duration) => parse_iso(duration).map(MifcTime::from_minutes),
+            None => parse_dhm(time)
+                .or_else(|| parse_with_units(time).map(MifcTime::from_minutes)),
+        }
+        .ok_or_else(|| TimeParseError(s.to_string()))
+    }
+}
+
+fn round_minutes(minutes: f64) -> f64 {
+    let scale = 10f64.powi(MIN_DECIMALS);
+    (minutes * scale).round() / scale + 0.0
+}
+
+/// "d", "d.h", or "d.h.m"
+fn parse_dhm(time: &str) -> Option<MifcTime> {
+    let mut parts = time.split('.');
+    let day  = parts.next()?.parse().ok()?;
+    let hour = parts.next().map(str::parse).unwrap_or(Ok(0.0)).ok()?;
+    let min  = parts.next().map(str::parse).unwrap_or(Ok(0.0)).ok()?;
+    if parts.next().is_some() { return None }
+
+    Some(MifcTime::from_dhm(day, hour, min))
+}
+
+/// Sum of numbers followed by a time unit, e.g. "1d 12h" or "1 day 30 minutes"
+fn parse_with_units(time: &str) -> Option<f64> {
+    sum_components(time, |unit| match unit.to_lowercase().as_str() {
+        "d" | "day" | "days" => Some(MINS_PER_DAY),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(MINS_PER_HOUR),
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(1.0),
+        "s" | "sec" | "secs" | "second" | "seconds" => Some(1.0 / 60.0),
+        _ => None,
+    })
+}
+
+/// The part of an ISO-8601 duration after the "P". Years and months are rejected,
+/// since they don't have a fixed length.
+fn parse_iso(duration: &str) -> Option<f64> {
+    let mut parts = duration.splitn(2, ['T', 't']);
+    let date = parts.next()?;
+    let time = parts.next();
+    if date.is_empty() && time.is_none_or(str::is_empty) { return None }
+
+    let date_mins = match date {
+        "" => 0.0,
+        d => sum_components(d, |unit| match unit {
+            "W" | "w" => Some(7.0 * MINS_PER_DAY),
+            "D" | "d" => Some(MINS_PER_DAY),
+            _ => None,
+        })?,
+    };
+    let time_mins = match time {
+        None => 0.0,
+        Some(t) => sum_components(t, |unit| match unit {
+            "H" | "h" => Some(MINS_PER_HOUR),
+            "M" | "m" => Some(1.0),
+            "S" | "s" => Some(1.0 / 60.0),
+            _ => None,
+        })?,
+    };
+
+    Some(date_mins + time_mins)
+}
+
+/// Add up every "<number><unit>" pair in `s`, using `unit_mins` for the minutes in each unit
+fn sum_components<F>(s: &str, unit_mins: F) -> Option<f64>
+where F: Fn(&str) -> Option<f64>
+{
+    let is_number = |c: char| c.is_ascii_digit() || c == '.' || c == '-';
+    let mut rest = s.trim_start();
+    let mut total = None;
+
+    while !rest.is_empty() {
+        let num_end = rest.find(|c: char| !is_number(c))?;
+        let value: f64 = rest[..num_end].parse().ok()?;
+        rest = rest[num_end..].trim_start();
+
+        let unit_end = rest.find(|c: char| is_number(c) || c.is_whitespace() || c == ',')
+            .unwrap_or(rest.len());
+        let mins = unit_mins(&rest[..unit_end])?;
+        rest = rest[unit_end..].trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+
+        total = Some(total.unwrap_or(0.0) + value * mins);
+    }
+
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_formats() {
+        let day_and_half = MifcTime::from_dhm(1.0, 12.0, 0.0);
+        for input in ["1.12.0", "1.12", "36h", "36 hours", "1d 12h", "1d12h", "1 day, 12 hours", "0.36", "P1DT12H", "PT36H", "pt2160m"].iter() {
+            assert_eq!(input.parse::<MifcTime>().unwrap(), day_and_half, "parsing {}", input);
+        }
+
+        assert_eq!("2".parse::<MifcTime>().unwrap(), MifcTime::from_dhm(2.0, 0.0, 0.0));
+        assert_eq!("P1W".parse::<MifcTime>().unwrap(), MifcTime::from_dhm(7.0, 0.0, 0.0));
+        assert_eq!("90 min".parse::<MifcTime>().unwrap().total_hours(), 1.5);
+
+        for bad in ["", "day", "1.2.3.4", "1 fortnight", "P1M", "P", "PT", "1..2"].iter() {
+            assert!(bad.parse::<MifcTime>().is_err(), "{} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn canonical_parts_and_ordering() {
+        let t = MifcTime::from_dhm(0.0, 36.0, 90.0);
+        assert_eq!(t.dhm(), (1.0, 13.0, 30.0));
+        assert_eq!(t.to_string(), "1.13.30");
+        assert_eq!(t.total_minutes(), 37.5 * 60.0);
+        assert_eq!(t.entered_dhm(), Some((0.0, 36.0, 90.0)));
+        assert_eq!("36h".parse::<MifcTime>().unwrap().entered_dhm(), None);
+
+        // entered parts are written back as they were, and canonical parts are rounded
+        assert_eq!(t.written_dhm(), (0.0, 36.0, 90.0));
+        let fractional = MifcTime::from_dhm(1.1, 0.0, 0.0);
+        assert_eq!(fractional.written_dhm(), (1.1, 0.0, 0.0));
+        assert_eq!(fractional.dhm(), (1.0, 2.0, 24.0));
+        assert_eq!(MifcTime::from_minutes(0.1 + 0.2).dhm(), (0.0, 0.0, 0.3));
+        assert_eq!(MifcTime::from_minutes(-90.0).dhm(), (0.0, -1.0, -30.0));
+
+        let mut times = [
+            MifcTime::from_dhm(2.0, 0.0, 0.0),
+            MifcTime::from_dhm(0.0, 1.0, 0.0),
+            MifcTime::from_dhm(1.0, 0.0, 0.0),
+        ];
+        times.sort();
+        assert_eq!(times[0].total_hours(), 1.0);
+        assert_eq!(times[2] - times[1], MifcTime::from_dhm(1.0, 0.0, 0.0));
+        assert_eq!(times[1] + times[0], MifcTime::from_minutes(25.0 * 60.0));
+    }
+}
@@ -0,0 +1,223 @@
+//! Checks for bad data in a row, reported as typed issues so that every tool
+//! can find the same problems and report them in its own way
+use std::fmt;
+use units::Unit;
+use crate::field::MifcField;
+use crate::mifc::{Mifc, MifcImage, MifcNorm};
+
+const MINS_PER_HOUR: f64 = 60.0;
+const HOURS_PER_DAY: f64 = 24.0;
+
+/// How serious an issue is
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum Severity {
+    /// The row can still be used, but should probably be checked
+    Warning,
+    /// The row is invalid
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// What is wrong with a field
+#[derive(Debug, Clone, PartialEq)]
+pub enum IssueKind {
+    Empty,
+    Negative(f64),
+    NotFinite,
+    /// The value and the limit that it reached
+    TooLarge(f64, f64),
+    MissingValue,
+    MissingUnit,
+    UnkUnit(String),
+    NotInteger(f64),
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::IssueKind::*;
+
+        match self {
+            Empty => write!(f, "is empty"),
+            Negative(v) => write!(f, "is negative ({})", v),
+            NotFinite => write!(f, "is not a finite number"),
+            TooLarge(v, limit) => write!(f, "is {}, which is {} or more", v, limit),
+            MissingValue => write!(f, "is missing, but the row isn't excluded"),
+            MissingUnit => write!(f, "is missing for a row with a Value"),
+            UnkUnit(u) => write!(f, "<{}> is not a known unit", u),
+            NotInteger(v) => write!(f, "{} is not a whole number", v),
+        }
+    }
+}
+
+/// A problem with one field of a row
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub field: MifcField,
+    pub severity: Severity,
+    pub kind: IssueKind,
+}
+
+impl Issue {
+    fn error(field: MifcField, kind: IssueKind) -> Self {
+        Issue { field, severity: Severity::Error, kind }
+    }
+
+    fn warning(field: MifcField, kind: IssueKind) -> Self {
+        Issue { field, severity: Severity::Warning, kind }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: \"{}\" {}", self.severity, self.field, self.kind)
+    }
+}
+
+fn check_text(issues: &mut Vec<Issue>, field: MifcField, text: &str) {
+    if text.trim().is_empty() {
+        issues.push(Issue::error(field, IssueKind::Empty));
+    }
+}
+
+fn check_number(issues: &mut Vec<Issue>, field: MifcField, n: f64) -> bool {
+    if !n.is_finite() {
+        issues.push(Issue::error(field, IssueKind::NotFinite));
+        false
+    } else if n < 0.0 {
+        issues.push(Issue::error(field, IssueKind::Negative(n)));
+        false
+    } else {
+        true
+    }
+}
+
+impl Mifc {
+    /// Check this row for bad data, returning every issue that was found
+    pub fn validate(&self) -> Vec<Issue> {
+        use self::MifcField::*;
+        let mut issues = Vec::new();
+
+        check_text(&mut issues, ChipId, &self.id);
+        check_text(&mut issues, Method, &self.method);
+        check_text(&mut issues, Target, &self.target);
+        check_text(&mut issues, SampleLocation, &self.sample_loc);
+
+        match self.time.entered_dhm() {
+            Some((day, hour, min)) => {
+                check_number(&mut issues, Day, day);
+                if check_number(&mut issues, Hour, hour) && hour >= HOURS_PER_DAY {
+                    issues.push(Issue::warning(Hour, IssueKind::TooLarge(hour, HOURS_PER_DAY)));
+                }
+                if check_number(&mut issues, Minute, min) && min >= MINS_PER_HOUR {
+                    issues.push(Issue::warning(Minute, IssueKind::TooLarge(min, MINS_PER_HOUR)));
+                }
+            },
+            None => { check_number(&mut issues, Day, self.time.total_days()); },
+        }
+
+        match self.value {
+            Some(v) if !v.is_finite() => issues.push(Issue::error(Value, IssueKind::NotFinite)),
+            Some(_) if self.value_unit.is_none() => issues.push(Issue::error(ValueUnit, IssueKind::MissingUnit)),
+            None if !self.exclude => issues.push(Issue::error(Value, IssueKind::MissingValue)),
+            _ => (),
+        }
+        if let Some(unit) = self.value_unit.as_ref() {
+            if unit.parse::<Unit>().is_err() {
+                issues.push(Issue::warning(ValueUnit, IssueKind::UnkUnit(unit.clone())));
+            }
+        }
+        if let Some(u) = self.uncertainty {
+            check_number(&mut issues, ValueUncertainty, u);
+        }
+        if let Some(r) = self.replicate {
+            let r = f64::from(r);
+            if r.fract() != 0.0 {
+                issues.push(Issue::warning(Replicate, IssueKind::NotInteger(r)));
+            }
+        }
+
+        issues
+    }
+}
+
+impl MifcImage {
+    /// Check this row's MIFC and image columns for bad data, returning every issue that was found
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = self.mifc.validate();
+        check_text(&mut issues, MifcField::ImageFileName, &self.file);
+        check_number(&mut issues, MifcField::ImageMagnification, self.magnification);
+        check_number(&mut issues, MifcField::ImageResolution, self.resolution);
+
+        issues
+    }
+}
+
+impl MifcNorm {
+    /// Check this row's MIFC columns for bad data, returning every issue that was found
+    pub fn validate(&self) -> Vec<Issue> {
+        self.mifc.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::MifcTime;
+
+    fn row() -> Mifc {
+        Mifc { replicate: Some(1.0), ..Mifc::test_row() }
+    }
+
+    fn found(issues: &[Issue], field: MifcField, severity: Severity) -> bool {
+        issues.iter().any(|i| i.field == field && i.severity == severity)
+    }
+
+    #[test]
+    fn valid_row() {
+        assert_eq!(row().validate(), vec![]);
+    }
+
+    #[test]
+    fn row_issues() {
+        let mut bad = row();
+        bad.id = "  ".to_string();
+        bad.time = MifcTime::from_dhm(-1.0, 2.0, 75.0);
+        bad.value_unit = Some("furlongs".to_string());
+        bad.replicate = Some(1.5);
+        let issues = bad.validate();
+
+        assert!(found(&issues, MifcField::ChipId, Severity::Error));
+        assert!(found(&issues, MifcField::Day, Severity::Error));
+        assert!(found(&issues, MifcField::Minute, Severity::Warning));
+        assert!(found(&issues, MifcField::ValueUnit, Severity::Warning));
+        assert!(found(&issues, MifcField::Replicate, Severity::Warning));
+        assert!(!found(&issues, MifcField::Hour, Severity::Warning));
+        assert_eq!(issues.len(), 5);
+    }
+
+    #[test]
+    fn missing_values() {
+        let mut missing = row();
+        missing.value = None;
+        assert!(found(&missing.validate(), MifcField::Value, Severity::Error));
+
+        missing.exclude = true;
+        assert_eq!(missing.validate(), vec![]);
+
+        let mut no_unit = row();
+        no_unit.value_unit = None;
+        assert!(found(&no_unit.validate(), MifcField::ValueUnit, Severity::Error));
+    }
+}
@@ -0,0 +1,378 @@
+//! Writing MIFC rows, either as CSV or as typed cells in an XLSX workbook
+use std::collections::{BTreeSet, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use failure::{Fail};
+use log::{warn};
+use rust_xlsxwriter::{Format, Workbook, XlsxError};
+use crate::field::MifcField;
+use crate::header::{ExtraColumns, normalize};
+use crate::mifc::Mifc;
+
+/// Excel's limit on the length of a sheet name
+const MAX_SHEET_NAME: usize = 31;
+/// Used for sheets that don't have a name of their own, like a sheet made from a CSV file
+const DEFAULT_SHEET_NAME: &str = "MIFC";
+
+#[derive(Debug, Fail)]
+pub enum WriteError {
+    #[fail(display = "couldn't write MIFC row as CSV")]
+    Csv(#[cause] csv::Error),
+    #[fail(display = "couldn't write XLSX workbook <{}>", _0)]
+    Xlsx(String, #[cause] XlsxError),
+}
+
+/// The file format of output MIFC files
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OutputFormat {
+    Csv,
+    Xlsx,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Xlsx => "xlsx",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "xlsx" => Ok(OutputFormat::Xlsx),
+            _ => Err(format!("unknown output format <{}>; expected csv or xlsx", s)),
+        }
+    }
+}
+
+/// Something that MIFC rows can be written to
+pub trait MifcWriter {
+    fn write_mifc(&mut self, row: &Mifc) -> Result<(), WriteError>;
+}
+
+/// The headers of the extra columns that are written after the MIFC columns, which are
+/// taken from the first row written. Later rows with other extra columns are reported
+/// once for each column, and those cells are left out.
+#[derive(Debug, Default)]
+struct ExtraHeaders {
+    headers: Vec<String>,
+    left_out: BTreeSet<String>,
+}
+
+impl ExtraHeaders {
+    /// The extra headers of the first row, besides any that would repeat a MIFC
+    /// column's header, like a "Sample Location" column in a CMPD file
+    fn new(first: &ExtraColumns) -> Self {
+        let mut left_out = BTreeSet::new();
+        let headers = first.headers()
+            .filter(|header| {
+                let repeated = MifcField::MIFC.iter().any(|f| normalize(f.header()) == normalize(header));
+                if repeated && left_out.insert(header.to_string()) {
+                    warn!("extra column <{}> has the same header as a MIFC column, so it was left out", header);
+                }
+                !repeated
+            })
+            .map(str::to_string)
+            .collect();
+
+        ExtraHeaders { headers, left_out }
+    }
+
+    /// The row's cell for each extra header, in order
+    fn cells<'r>(&mut self, extra: &'r ExtraColumns) -> Vec<&'r str> {
+        for header in extra.headers() {
+            if !self.headers.iter().any(|h| h == header) && self.left_out.insert(header.to_string()) {
+                warn!("extra column <{}> isn't in the output's header, so it was left out", header);
+            }
+        }
+
+        self.headers.iter().map(|h| extra.get(h).unwrap_or("")).collect()
+    }
+}
+
+/// A CSV file of MIFC rows, with any extra columns written after the MIFC columns
+pub struct CsvWriter<W: Write> {
+    wtr: csv::Writer<W>,
+    /// Set once the header has been written
+    extra: Option<ExtraHeaders>,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(wtr: W) -> Self {
+        CsvWriter {
+            wtr: csv::WriterBuilder::new().has_headers(false).from_writer(wtr),
+            extra: None,
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<(), WriteError> {
+        self.wtr.flush().map_err(|e| WriteError::Csv(e.into()))
+    }
+}
+
+impl<W: Write> MifcWriter for CsvWriter<W> {
+    fn write_mifc(&mut self, row: &Mifc) -> Result<(), WriteError> {
+        let wtr = &mut self.wtr;
+        let extra = match self.extra.as_mut() {
+            Some(extra) => extra,
+            None => {
+                let extra = ExtraHeaders::new(&row.extra);
+                let header: Vec<&str> = MifcField::MIFC.iter()
+                    .map(|f| f.header())
+                    .chain(extra.headers.iter().map(String::as_str))
+                    .collect();
+                wtr.write_record(&header).map_err(WriteError::Csv)?;
+                self.extra.get_or_insert(extra)
+            },
+        };
+
+        wtr.serialize((row, extra.cells(&row.extra))).map_err(WriteError::Csv)
+    }
+}
+
+/// An XLSX workbook of MIFC rows. Each sheet starts with a bold, frozen header row.
+/// Numeric columns are written as numbers, and every other column as text, so that
+/// Chip IDs like "007" keep their leading zeros.
+pub struct XlsxWriter {
+    path: PathBuf,
+    workbook: Workbook,
+    header: Format,
+    /// Lowercase names of the sheets so far, since Excel sheet names are case-insensitive
+    names: HashSet<String>,
+    /// Index of the sheet being written, and its next empty row
+    current: Option<(usize, u32)>,
+    /// Headers of the current sheet's extra columns, once its first row is written
+    extra: Option<ExtraHeaders>,
+}
+
+impl XlsxWriter {
+    /// Start a workbook that will be saved to `path`
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        XlsxWriter {
+            path: path.as_ref().to_path_buf(),
+            workbook: Workbook::new(),
+            header: Format::new().set_bold(),
+            names: HashSet::new(),
+            current: None,
+            extra: None,
+        }
+    }
+
+    /// Start a new sheet, which following rows are written to. The name is changed,
+    /// if needed, into a valid and unique Excel sheet name.
+    pub fn add_sheet(&mut self, name: &str) -> Result<(), WriteError> {
+        let name = self.unique_name(name);
+        let index = self.names.len();
+        self.names.insert(name.to_lowercase());
+
+        let err = xlsx_err(&self.path);
+        let sheet = self.workbook.add_worksheet();
+        sheet.set_name(name)
+            .and_then(|s| s.set_freeze_panes(1, 0))
+            .map_err(&err)?;
+        for (col, field) in MifcField::MIFC.iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16, field.header(), &self.header)
+                .map_err(&err)?;
+        }
+
+        self.current = Some((index, 1));
+        self.extra = None;
+        Ok(())
+    }
+
+    /// Save the workbook. A workbook without any rows still gets an empty MIFC sheet.
+    pub fn save(mut self) -> Result<(), WriteError> {
+        if self.current.is_none() {
+            self.add_sheet(DEFAULT_SHEET_NAME)?;
+        }
+        let err = xlsx_err(&self.path);
+        for i in 0..self.names.len() {
+            self.workbook.worksheet_from_index(i).map_err(&err)?.autofit();
+        }
+
+        self.workbook.save(&self.path).map_err(err)
+    }
+
+    /// Turn `name` into a sheet name that Excel accepts and that isn't used yet
+    fn unique_name(&self, name: &str) -> String {
+        let cleaned: String = name.trim()
+            .trim_matches('\'')
+            .chars()
+            .map(|c| if "[]:*?/\\".contains(c) { '_' } else { c })
+            .take(MAX_SHEET_NAME)
+            .collect();
+        let base = match cleaned.trim() {
+            "" => DEFAULT_SHEET_NAME.to_string(),
+            b => b.to_string(),
+        };
+
+        let mut name = base.clone();
+        let mut n = 2;
+        while self.names.contains(&name.to_lowercase()) {
+            let suffix = format!(" ({})", n);
+            let keep = MAX_SHEET_NAME - suffix.chars().count();
+            name = base.chars().take(keep).collect::<String>() + &suffix;
+            n += 1;
+        }
+
+        name
+    }
+}
+
+fn xlsx_err(path: &Path) -> impl Fn(XlsxError) -> WriteError {
+    let path = path.display().to_string();
+    move |e| WriteError::Xlsx(path.clone(), e)
+}
+
+impl MifcWriter for XlsxWriter {
+    /// Write a row into the current sheet, starting a default sheet if there isn't one.
+    /// Extra columns are written after the MIFC columns, with headers from the sheet's first row.
+    fn write_mifc(&mut self, mifc: &Mifc) -> Result<(), WriteError> {
+        use self::MifcField::*;
+
+        if self.current.is_none() {
+            self.add_sheet(DEFAULT_SHEET_NAME)?;
+        }
+        let (index, row) = self.current.expect("a sheet was just added");
+        let (day, hour, min) = mifc.time.written_dhm();
+        let err = xlsx_err(&self.path);
+        let sheet = self.workbook.worksheet_from_index(index).map_err(&err)?;
+
+        let first_col = MifcField::MIFC.len() as u16;
+        let extra = match self.extra.as_mut() {
+            Some(extra) => extra,
+            None => {
+                let extra = ExtraHeaders::new(&mifc.extra);
+                for (col, header) in extra.headers.iter().enumerate() {
+                    sheet.write_string_with_format(0, first_col + col as u16, header, &self.header)
+                        .map_err(&err)?;
+                }
+                self.extra.get_or_insert(extra)
+            },
+        };
+        for (col, cell) in extra.cells(&mifc.extra).into_iter().enumerate() {
+            if !cell.is_empty() {
+                sheet.write_string(row, first_col + col as u16, cell).map_err(&err)?;
+            }
+        }
+
+        for (col, field) in MifcField::MIFC.iter().enumerate() {
+            let col = col as u16;
+            let number = match field {
+                Day => Some(day),
+                Hour => Some(hour),
+                Minute => Some(min),
+                Value => mifc.value,
+                Replicate => mifc.replicate.map(f64::from),
+                ValueUncertainty => mifc.uncertainty,
+                _ => None,
+            };
+            let written = match (number, mifc.get_field(*field)) {
+                (Some(n), _) => sheet.write_number(row, col, n).map(|_| ()),
+                (None, Some(text)) => sheet.write_string(row, col, text).map(|_| ()),
+                (None, None) => Ok(()),
+            };
+            written.map_err(&err)?;
+        }
+
+        self.current = Some((index, row + 1));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::{DataType, Reader, Xlsx, open_workbook};
+    use crate::time::MifcTime;
+
+    fn row(id: &str, value: Option<f64>) -> Mifc {
+        Mifc {
+            id: id.to_string(),
+            time: MifcTime::from_dhm(1.0, 12.0, 0.0),
+            value,
+            value_unit: Some("µg/mL".to_string()),
+            exclude: value.is_none(),
+            replicate: Some(2.0),
+            ..Mifc::test_row()
+        }
+    }
+
+    #[test]
+    fn csv_extra_columns() {
+        let mut first = row("C1", Some(1.5));
+        first.extra.push("Operator", "MC");
+        first.extra.push("Lot", "7");
+        let mut second = row("C2", Some(2.0));
+        second.extra.push("Lot", "8");
+        second.extra.push("Barcode", "P-01");
+
+        let mut wtr = CsvWriter::new(Vec::new());
+        wtr.write_mifc(&first).unwrap();
+        wtr.write_mifc(&second).unwrap();
+        let text = String::from_utf8(wtr.wtr.into_inner().unwrap()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert!(lines[0].ends_with("Cross Reference,Value Uncertainty,Operator,Lot"));
+        assert!(lines[1].starts_with("C1,") && lines[1].ends_with(",MC,7"));
+        assert!(lines[2].starts_with("C2,") && lines[2].ends_with(",,8"));
+        assert_eq!(lines.len(), 3);
+
+        // an extra column can't repeat a MIFC column's header
+        let mut cmpd = row("C3", Some(1.0));
+        cmpd.extra.push("sample location", "apical");
+        cmpd.extra.push("Lot", "9");
+        let mut wtr = CsvWriter::new(Vec::new());
+        wtr.write_mifc(&cmpd).unwrap();
+        let text = String::from_utf8(wtr.wtr.into_inner().unwrap()).unwrap();
+        assert!(text.lines().next().unwrap().ends_with("Value Uncertainty,Lot"));
+        assert!(text.lines().nth(1).unwrap().ends_with(",9"));
+    }
+
+    #[test]
+    fn sheet_names() {
+        let mut wtr = XlsxWriter::new("unused.xlsx");
+        assert_eq!(wtr.unique_name("Day 1/2"), "Day 1_2");
+        assert_eq!(wtr.unique_name("  "), "MIFC");
+        assert_eq!(wtr.unique_name(&"x".repeat(40)).chars().count(), 31);
+
+        wtr.add_sheet("Results").unwrap();
+        assert_eq!(wtr.unique_name("results"), "results (2)");
+    }
+
+    #[test]
+    fn typed_cells_and_sheets() {
+        let path = std::env::temp_dir().join(format!("sd3-write-{}.xlsx", std::process::id()));
+        let mut wtr = XlsxWriter::new(&path);
+        wtr.add_sheet("Plate 1").unwrap();
+        let mut first = row("007", Some(12.5));
+        first.extra.push("Operator", "MC");
+        wtr.write_mifc(&first).unwrap();
+        wtr.add_sheet("Plate 2").unwrap();
+        wtr.write_mifc(&row("008", None)).unwrap();
+        wtr.save().unwrap();
+
+        let mut wb: Xlsx<_> = open_workbook(&path).unwrap();
+        assert_eq!(wb.sheet_names().to_vec(), vec!["Plate 1", "Plate 2"]);
+        let first = wb.worksheet_range("Plate 1").unwrap().unwrap();
+        let second = wb.worksheet_range("Plate 2").unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(first.get_value((0, 0)), Some(&DataType::String("Chip ID".to_string())));
+        assert_eq!(first.get_value((1, 0)), Some(&DataType::String("007".to_string())));
+        assert_eq!(first.get_value((1, 8)), Some(&DataType::Float(12.0)));
+        assert_eq!(first.get_value((1, 10)), Some(&DataType::Float(12.5)));
+        assert_eq!(first.get_value((1, 11)), Some(&DataType::String("µg/mL".to_string())));
+        assert_eq!(second.get_value((1, 10)), Some(&DataType::Empty));
+        assert_eq!(first.get_value((0, 18)), Some(&DataType::String("Operator".to_string())));
+        assert_eq!(first.get_value((1, 18)), Some(&DataType::String("MC".to_string())));
+        assert_eq!(second.get_value((1, 13)), Some(&DataType::String("X".to_string())));
+        assert_eq!(second.get_size().1, 18);
+    }
+}
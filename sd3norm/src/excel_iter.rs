@@ -1,31 +1,40 @@
 use walkdir::WalkDir;
 use std::path::{PathBuf, Path};
+use sd3::io::Format;
 
 /// Convert of a collection of input files and/or directories into an iterator
-/// of just excel workbooks (.xls, .xlsm, .xlsx)
-pub fn all_workbooks<'a>(inputs: &'a [PathBuf]) -> impl Iterator<Item = PathBuf> + 'a
+/// of just workbooks and CSV files that `sd3::io` can read (.xls, .xlsm, .xlsx, .ods, .csv, ...).
+/// Files found in a directory are skipped if they look like this tool's output: if their
+/// name ends with "-`append`", or they're in `out_dir` (when the directory being searched
+/// isn't in `out_dir` itself). Files that are passed in are always read.
+pub fn all_workbooks<'a>(inputs: &'a [PathBuf], append: &'a str, out_dir: Option<&'a Path>) -> impl Iterator<Item = PathBuf> + 'a
 {
+    let out_dir = out_dir.and_then(|d| d.canonicalize().ok());
+
     inputs.iter()
-        .flat_map(|entry| { 
+        .flat_map(move |entry| { 
+            let out_dir = out_dir.clone()
+                .filter(|dir| !entry.canonicalize().is_ok_and(|e| e.starts_with(dir)));
             WalkDir::new(&entry)
             .into_iter()
             .filter_map(|e| e.ok())
+            .filter(move |e| e.depth() == 0 || !is_output(e.path(), append, out_dir.as_deref()))
             .map(|e| e.path().to_path_buf())
         })
-        .filter(is_excel)
+        .filter(|p| Format::from_path(p).is_some())
         .filter(is_not_excel_temp)
 }
 
-/// Check the extension of a Path to see if it is an excel workbook
-fn is_excel<P: AsRef<Path>>(file: &P) -> bool {
-    if let Some(ex) = file.as_ref().extension() {
-        match &*ex.to_string_lossy() {
-            "xlsx" => true,
-            "xls" => true,
-            "xlsm" => true,
-            _ => false,
-        }
-    } else { false }
+/// Check if a file is probably an earlier output of this tool
+fn is_output(file: &Path, append: &str, out_dir: Option<&Path>) -> bool {
+    let suffix = format!("-{}", append);
+    let has_suffix = file.file_stem()
+        .is_some_and(|s| s.to_string_lossy().ends_with(&suffix));
+    let in_out_dir = out_dir.is_some_and(|dir| {
+        file.canonicalize().is_ok_and(|f| f.starts_with(dir))
+    });
+
+    has_suffix || in_out_dir
 }
 
 /// Check if an excel file is a not temp file
@@ -2,21 +2,23 @@ mod excel_iter;
 
 use failure::{Error, ResultExt, bail, format_err};
 use structopt::StructOpt;
-use log::{error, warn, info, debug};
+use log::{error, warn, info, debug, trace};
 use flexi_logger::{Logger, default_format};
-use calamine::{Reader, RangeDeserializerBuilder, open_workbook_auto};
 
 use std::path::{Path, PathBuf};
 use std::fmt;
+use std::io;
 use std::fs::{OpenOptions, self};
 use std::ffi::{OsStr};
+use std::collections::BTreeSet;
 
-use sd3::MifcNorm;
+use sd3::{BlankRules, Blanks, CellCounts, ColumnAliases, CsvWriter, ExclusionRules, Interpolation, Layout, MifcNorm, MifcTime, MifcWriter, MolecularWeights, NormBasis, NormInfoTable, NormStrategy, OutputFormat, Severity, XlsxWriter};
+use sd3::io::Located;
 
 #[derive(StructOpt, Debug)]
-/// Read an MIFC + normalization info excel workbook and create one normalized MIFC CSV for each sheet
+/// Read an MIFC + normalization info workbook (Excel, ODS) or CSV file and create one normalized MIFC CSV for each sheet
 struct Opt {
-    /// Any number of input mifc+normalization-formatted excel files or directories containing excel files
+    /// Any number of input mifc+normalization-formatted excel, ODS, or CSV files or directories containing those files
     #[structopt(name = "INPUT", parse(from_os_str))]
     input: Vec<PathBuf>,
     /// Append to INPUT for output, defaults to "normalized"
@@ -28,6 +30,59 @@ struct Opt {
     /// Directory to create output file(s) in
     #[structopt(short = "d", long = "out-dir", parse(from_os_str))]
     out_dir: Option<PathBuf>, 
+    /// TOML or CSV file of extra unit spellings (alias -> unit) to accept
+    #[structopt(long = "unit-aliases", parse(from_os_str))]
+    unit_aliases: Option<PathBuf>,
+    /// Print every known unit, with its type, aliases, and SI factor, as a table or json and exit
+    #[structopt(long = "list-units", raw(possible_values = r#"&["table", "json"]"#))]
+    list_units: Option<units::CatalogFormat>,
+    /// CSV or TOML file of molecular weights (g/mol) by Target/Analyte, used for molar rows
+    /// (or mass rows, with a molar normalization unit) without a Molecular Weight (g/mol)
+    #[structopt(long = "mw-file", parse(from_os_str))]
+    mw_file: Option<PathBuf>,
+    /// TOML or CSV file of which Caution Flag codes (O, W, F) exclude a row; by default, every code does
+    #[structopt(long = "flag-rules", parse(from_os_str))]
+    flag_rules: Option<PathBuf>,
+    /// TOML or CSV file of other spellings of column headers (alias -> column)
+    #[structopt(long = "column-aliases", parse(from_os_str))]
+    column_aliases: Option<PathBuf>,
+    /// Write each output as CSV files (one per sheet), or as one XLSX workbook with a sheet for each input sheet
+    #[structopt(long = "format", default_value = "csv", raw(possible_values = r#"&["csv", "xlsx"]"#))]
+    format: OutputFormat,
+    /// What each Value is normalized by: per cell per day, per day, the total mass, or per cell
+    #[structopt(long = "normalize", default_value = "cell-rate",
+        raw(possible_values = r#"&["cell-rate", "rate", "mass", "per-cell"]"#))]
+    normalize: NormBasis,
+    /// Unit of the normalized Values, e.g. "pg/day/10^3 cells"; defaults to ng/day/10^6 cells,
+    /// ng/day, ng, or ng/10^6 cells, depending on --normalize
+    #[structopt(long = "norm-unit")]
+    norm_unit: Option<units::Unit>,
+    /// Normalize into an amount of each target (e.g. pmol/day/10^6 cells) instead of a mass;
+    /// rows with a mass Value Unit need a molecular weight
+    #[structopt(long = "molar", raw(conflicts_with = r#""norm_unit""#))]
+    molar: bool,
+    /// Chip ID of blank (e.g. fresh media) rows, which are subtracted from the other rows
+    /// with the same method, target, time, and plate before normalizing
+    #[structopt(long = "blank-id", number_of_values = 1)]
+    blank_ids: Vec<String>,
+    /// Sample Location of blank rows, like --blank-id
+    #[structopt(long = "blank-location", number_of_values = 1)]
+    blank_locations: Vec<String>,
+    /// CSV or workbook file of normalization info by Chip ID and Day (with optional Hour and Minute),
+    /// used for rows without their own normalization columns
+    #[structopt(long = "norm-info", parse(from_os_str))]
+    norm_info: Option<PathBuf>,
+    /// How far apart a row's time and its normalization info's time can be, e.g. 12h or 1d
+    #[structopt(long = "info-tolerance", default_value = "0")]
+    info_tolerance: MifcTime,
+    /// CSV or workbook file of cell counts by Chip ID and Day (with optional Hour and Minute),
+    /// which are interpolated into the Estimated Cell Number of each row
+    #[structopt(long = "cell-counts", parse(from_os_str))]
+    cell_counts: Option<PathBuf>,
+    /// How cell counts are estimated between the counted times
+    #[structopt(long = "cell-interpolation", default_value = "linear",
+        raw(possible_values = r#"&["linear", "log-linear", "locf"]"#))]
+    cell_interpolation: Interpolation,
 }
 
 
@@ -69,8 +124,78 @@ fn run(opts: Opt) -> Result<(), Error> {
     debug!("Output directory: {:?}", output_directory);
     debug!("output append: {}", &append_str);
 
+    if let Some(aliases) = opts.unit_aliases.as_ref() {
+        let n = units::load_aliases(aliases)
+            .context(format!("loading unit aliases from <{:?}>", aliases))?;
+        info!("loaded {} unit alias(es) from {:?}", n, aliases);
+    }
+    if let Some(format) = opts.list_units {
+        units::write_catalog(io::stdout(), format).context("printing unit list")?;
+        return Ok(());
+    }
+    let weights = match opts.mw_file.as_ref() {
+        Some(path) => {
+            let weights = MolecularWeights::from_path(path)
+                .context(format!("loading molecular weights from <{:?}>", path))?;
+            info!("loaded {} molecular weight(s) from {:?}", weights.len(), path);
+            weights
+        },
+        None => MolecularWeights::new(),
+    };
+    let flag_rules = match opts.flag_rules.as_ref() {
+        Some(path) => ExclusionRules::from_path(path)
+            .context(format!("loading flag rules from <{:?}>", path))?,
+        None => ExclusionRules::default(),
+    };
+    let column_aliases = match opts.column_aliases.as_ref() {
+        Some(path) => {
+            let aliases = ColumnAliases::from_path(path)
+                .context(format!("loading column aliases from <{:?}>", path))?;
+            info!("loaded {} column alias(es) from {:?}", aliases.len(), path);
+            aliases
+        },
+        None => ColumnAliases::new(),
+    };
+    let strategy = match opts.norm_unit {
+        Some(unit) => NormStrategy::new(opts.normalize).with_unit(unit)?,
+        None if opts.molar => NormStrategy::molar(opts.normalize),
+        None => NormStrategy::new(opts.normalize),
+    };
+    info!("normalizing values by {} into {}", strategy.basis(), strategy.unit());
+    let mut blank_rules = BlankRules::new();
+    for id in opts.blank_ids.iter() {
+        blank_rules.add_chip_id(id);
+    }
+    for location in opts.blank_locations.iter() {
+        blank_rules.add_location(location);
+    }
+    let norm_info = match opts.norm_info.as_ref() {
+        Some(path) => {
+            let table = NormInfoTable::from_path(path, &column_aliases)
+                .context(format!("loading normalization info from <{:?}>", path))?
+                .with_tolerance(opts.info_tolerance);
+            info!("loaded {} normalization info entries from {:?}", table.len(), path);
+            Some(table)
+        },
+        None => None,
+    };
+    let cell_counts = match opts.cell_counts.as_ref() {
+        Some(path) => {
+            let counts = CellCounts::from_path(path, &column_aliases)
+                .context(format!("loading cell counts from <{:?}>", path))?
+                .with_method(opts.cell_interpolation);
+            info!("loaded {} cell counts from {:?}, interpolated by {}", counts.len(), path, counts.method());
+            Some(counts)
+        },
+        None => None,
+    };
+    let settings = Settings {
+        weights, flag_rules, column_aliases, format: opts.format, strategy, blank_rules, norm_info, cell_counts,
+    };
+    let mut summary = Summary::default();
+
     /* Convert collection of input files and/or directories into a "output/workbook" pathbuf iterator */
-    let workbooks = excel_iter::all_workbooks(&inputs)
+    let workbooks = excel_iter::all_workbooks(&inputs, append_str, output_directory)
         .map(|wb| {
             let out = generate_output_base(&wb, output_directory);
             (wb, out, &append_str)
@@ -80,7 +205,7 @@ fn run(opts: Opt) -> Result<(), Error> {
     for (wb, out, app) in workbooks {
         match out {
             Ok(out) =>
-                match normalize_workbook(&wb, &out, &app) {
+                match normalize_workbook(&wb, &out, app, &settings, &mut summary) {
                     Ok(_) => (),
                     Err(e) => {
                         warn!("Couldn't normalize workbook <{}> due to:\n{}", wb.display(), e);
@@ -94,80 +219,239 @@ fn run(opts: Opt) -> Result<(), Error> {
         }
     }
 
+    if !summary.missing_targets.is_empty() {
+        warn!("rows that needed a Molecular Weight (g/mol) had none for target(s): {}",
+            summary.missing_targets.into_iter().collect::<Vec<_>>().join(", "));
+    }
+    if summary.unmatched_info > 0 {
+        warn!("{} row(s) had no matching normalization info and weren't normalized", summary.unmatched_info);
+    }
+    if summary.uncounted > 0 {
+        warn!("{} row(s) couldn't have their cell number interpolated and kept their own Estimated Cell Number, if any", summary.uncounted);
+    }
+
     Ok(())
 }
 
-fn normalize_workbook<P, O>(wb_path: P, output_base: O, append: &str) -> Result<(), Error>
+/// The options loaded from the command line that are used for every workbook
+struct Settings {
+    weights: MolecularWeights,
+    flag_rules: ExclusionRules,
+    column_aliases: ColumnAliases,
+    format: OutputFormat,
+    strategy: NormStrategy,
+    blank_rules: BlankRules,
+    norm_info: Option<NormInfoTable>,
+    cell_counts: Option<CellCounts>,
+}
+
+/// Problems with rows that are reported after every workbook is normalized
+#[derive(Default)]
+struct Summary {
+    missing_targets: BTreeSet<String>,
+    unmatched_info: usize,
+    uncounted: usize,
+}
+
+fn normalize_workbook<P, O>(
+    wb_path: P,
+    output_base: O,
+    append: &str,
+    settings: &Settings,
+    summary: &mut Summary,
+) -> Result<(), Error>
 where P: AsRef<Path> + fmt::Debug,
       O: AsRef<Path> + fmt::Debug
 {
-    let mut workbook = open_workbook_auto(&wb_path)
-        .context(format!("opening excel workbook <{:?}>", &wb_path))?;
-    /* Iterate over the sheets in a workbook */
-    let sheets = workbook.sheet_names().to_vec();
+    /* Read every sheet in a workbook (or the single sheet of a CSV file) */
+    let sheets = sd3::io::open(&wb_path)
+        .context(format!("opening input <{:?}>", &wb_path))?
+        .with_aliases(&settings.column_aliases)
+        .sheets()
+        .collect::<Result<Vec<_>, _>>()?;
     let sheet_sum = sheets.len();
 
-    for (i, s) in sheets.iter().enumerate() {
-        let sheet = workbook.worksheet_range(s).unwrap()?;
-
-        /* Generate a writer to output the normalized values from this sheet 
-         * If there is only one sheet, don't append the sheet name to the output file name
-        **/
-        let output = {
-            let mut out = output_base.as_ref().to_path_buf();
-            let add_sheet = sheet_sum > 1;
-            let appended_info = format!("{s_h}{s}-{a}", 
-                s_h = if add_sheet {"-"} else {""},
-                s = if add_sheet {s} else {""},
-                a =  append
-            );
-            append_file_name(&mut out, &appended_info);
-            out
-        };
+    /* XLSX output is one workbook for each input, with a sheet for each input sheet */
+    let Settings { weights, flag_rules, format, strategy, blank_rules, norm_info, cell_counts, .. } = settings;
+    let mut xlsx = match format {
+        OutputFormat::Xlsx => {
+            let mut out = output_base.as_ref().with_extension(format.extension());
+            append_file_name(&mut out, format!("-{}", append));
+            info!("{:?}:\nOutput file: {:?}", &wb_path, &out);
+            Some(XlsxWriter::new(out))
+        },
+        OutputFormat::Csv => None,
+    };
+    let default_sheet = wb_path.as_ref().file_stem().map(|s| s.to_string_lossy().into_owned());
 
-        info!("{:?} - {} (#{}):\nOutput file: {:?}", &wb_path, s, i, &output);
+    for (i, sheet) in sheets.iter().enumerate() {
+        let s = sheet.name().unwrap_or("");
 
-        let mut wtr = csv::Writer::from_writer(
-            OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&output)?
-        );
+        /* Check that the sheet is an SD3 normalization sheet before reading any rows */
+        let headers = sheet.headers();
+        if headers.iter().all(|h| h.trim().is_empty()) {
+            debug!("skipping empty sheet <{}>", s);
+            continue;
+        }
+        let place = match sheet.name() {
+            Some(name) => format!("sheet <{}> of {:?}", name, &wb_path),
+            None => format!("{:?}", &wb_path),
+        };
+        for column in sheet.columns().renamed() {
+            info!("{}: {}", place, column);
+        }
+        /* With a normalization info file, plain MIFC sheets can be normalized too */
+        let layout = match sd3::check_layout(&headers, Layout::MifcNorm) {
+            Err(_) if norm_info.is_some() => sd3::check_layout(&headers, Layout::Mifc),
+            layout => layout,
+        };
+        match layout {
+            Ok(report) => if !report.unexpected.is_empty() {
+                info!("{} has extra column(s), which are written after the MIFC columns: {}", place, report.unexpected.join(", "));
+            },
+            Err(e) => {
+                warn!("skipping {}: {}", place, e);
+                continue;
+            },
+        }
+        /* Rates per cell need an Estimated Cell Number, unless one is interpolated or comes from the info file */
+        if strategy.basis().per_cell() && cell_counts.is_none() && norm_info.is_none()
+            && !headers.iter().any(|h| h == "Estimated Cell Number") {
+            warn!("skipping {}: normalizing per cell needs an Estimated Cell Number column, or --cell-counts", place);
+            continue;
+        }
 
         /* Deserialize the data into SD3 struct, then normalize each possible row, and serialize into output*/
-        let rows = match RangeDeserializerBuilder::new()
-            .has_headers(true)
-            .from_range(&sheet)
-        {
+        let rows = match sheet.records_with_extra::<MifcNorm>() {
             Ok(r) => r,
             Err(e) => {
                 warn!("issue parsing sheet <{}> into MIFC normalization format\n{}", s, e);
-                fs::remove_file(&output)?;
                 continue;
             } 
         };
 
-        for (i, result) in rows.enumerate() {
-            let record: MifcNorm = match result {
+        /* Generate a writer to output the normalized values from this sheet 
+         * For CSV output, if there is only one sheet, don't append the sheet name to the output file name
+        **/
+        let mut csv_wtr;
+        let wtr: &mut dyn MifcWriter = match xlsx.as_mut() {
+            Some(book) => {
+                book.add_sheet(sheet.name().or(default_sheet.as_deref()).unwrap_or(""))?;
+                book
+            },
+            None => {
+                let output = {
+                    let mut out = output_base.as_ref().to_path_buf();
+                    let add_sheet = sheet_sum > 1;
+                    let appended_info = format!("{s_h}{s}-{a}", 
+                        s_h = if add_sheet {"-"} else {""},
+                        s = if add_sheet {s} else {""},
+                        a =  append
+                    );
+                    append_file_name(&mut out, &appended_info);
+                    out
+                };
+
+                info!("{:?} - {} (#{}):\nOutput file: {:?}", &wb_path, s, i, &output);
+
+                csv_wtr = CsvWriter::new(
+                    OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(&output)?
+                );
+                &mut csv_wtr
+            },
+        };
+
+        let mut records = Vec::new();
+        for result in rows {
+            let Located { location: row, mut record } = match result {
                 Ok(r) => r,
                 Err(e) => {
-                    info!("couldn't deserializing row {} in {}:\n{}", i+2, s, e); 
+                    info!("{}", e); 
                     continue;
                 },
             };
+            if let Some(table) = norm_info {
+                match record.join_info(table) {
+                    Ok(true) => trace!("joined normalization info onto {}", row),
+                    Ok(false) => (),
+                    Err(e) => {
+                        warn!("{}: {}", row, e);
+                        summary.unmatched_info += 1;
+                    },
+                }
+            }
+            for issue in record.validate() {
+                match issue.severity {
+                    Severity::Error => warn!("{}: {}", row, issue),
+                    Severity::Warning => info!("{}: {}", row, issue),
+                }
+            }
+            record.apply_flag_rules(flag_rules);
+            match record.fill_mol_weight(weights, strategy) {
+                Ok(Some(mw)) => debug!("using a molecular weight of {} g/mol for {}", mw, row),
+                Ok(None) => (),
+                Err(sd3::MolWeightError::MissingTarget(target)) => {
+                    if !weights.is_empty() {
+                        warn!("{} needs a molecular weight, but target <{}> has none", row, target);
+                    }
+                    summary.missing_targets.insert(target);
+                },
+                Err(e) => warn!("couldn't look up a molecular weight for {}:\n{}", row, e),
+            }
+            records.push((row, record));
+        }
+
+        /* Blank rows are subtracted from the other rows of the sheet, and aren't written */
+        let mut blanks = Blanks::new();
+        records.retain(|(row, record)| {
+            if !record.is_blank(blank_rules) { return true; }
+            if !record.add_to(&mut blanks) {
+                info!("{} is a blank without a Value that can be subtracted", row);
+            }
+            false
+        });
+        if !blank_rules.is_empty() {
+            info!("{}: found {} blank row(s)", place, blanks.len());
+        }
 
-            let normalized_row = match record.into_normalized() {
+        for (row, mut record) in records {
+            if !blank_rules.is_empty() {
+                match record.subtract_blank(&blanks) {
+                    Ok(Some(blank)) => debug!("subtracted a blank of {} from {}", blank, row),
+                    Ok(None) => (),
+                    Err(e) => warn!("{} was normalized without subtracting a blank: {}", row, e),
+                }
+            }
+            if let Some(counts) = cell_counts {
+                match record.interpolate_cells(counts) {
+                    Ok(Some(cells)) => trace!("estimated {} cells for {}", cells, row),
+                    Ok(None) => (),
+                    Err(e) => {
+                        warn!("{}: {}", row, e);
+                        summary.uncounted += 1;
+                    },
+                }
+            }
+
+            let normalized_row = match record.into_normalized(strategy) {
                 Ok(n) => n,
                 Err(e) => {
-                    info!("did not normalize row {} in {}:\n{}", i+2, s, e);
+                    info!("did not normalize {}:\n{}", row, e);
                     continue;
                 },
             };
 
-            wtr.serialize(normalized_row)?;
+            wtr.write_mifc(&normalized_row)?;
         }
     }
+    if let Some(book) = xlsx {
+        book.save()?;
+    }
+
     Ok(())
 }
 
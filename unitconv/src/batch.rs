@@ -0,0 +1,129 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use failure::{Error, ResultExt, format_err};
+use log::{warn, info};
+use csv::StringRecord;
+
+use units::Unit;
+
+/// Convert the value column of a CSV file into `to`, writing every input column
+/// plus "Converted Value" and "Converted Unit" columns. Rows that can't be converted
+/// are written with empty converted columns.
+pub fn convert_csv(input: &Path, to: Unit, options: &crate::Opt) -> Result<(), Error> {
+    let rdr = csv::Reader::from_path(input)
+        .context(format!("opening batch input <{:?}>", input))?;
+
+    let output: Box<dyn Write> = match options.output.as_ref() {
+        Some(o) => Box::new(File::create(o).context(format!("creating output <{:?}>", o))?),
+        None => Box::new(io::stdout()),
+    };
+    let mut wtr = csv::Writer::from_writer(output);
+
+    let skipped = convert_records(rdr, &mut wtr, to, options)
+        .context(format!("converting batch input <{:?}>", input))?;
+    info!("{} row(s) could not be converted", skipped);
+
+    Ok(())
+}
+
+/// Convert every record of `rdr` into `wtr`, returning the number of rows that couldn't be converted
+fn convert_records<R: io::Read, W: Write>(mut rdr: csv::Reader<R>, wtr: &mut csv::Writer<W>, to: Unit, options: &crate::Opt) -> Result<usize, Error> {
+    let headers = rdr.headers()?.clone();
+    let column = |name: &str| {
+        headers.iter()
+            .position(|h| h.trim() == name)
+            .ok_or_else(|| format_err!("missing column <{}>", name))
+    };
+    let value_col = column(&options.value_col)?;
+    let unit_col = column(&options.unit_col)?;
+
+    let mut out_headers = headers.clone();
+    out_headers.push_field("Converted Value");
+    out_headers.push_field("Converted Unit");
+    wtr.write_record(&out_headers)?;
+
+    let mut skipped = 0;
+    for (i, result) in rdr.records().enumerate() {
+        let mut record = result.context(format!("reading row {} of batch input", i + 2))?;
+
+        match convert_record(&record, value_col, unit_col, to, options.mol_weight) {
+            Ok(v) => {
+                record.push_field(&v.to_string());
+                record.push_field(&to.to_string());
+            },
+            Err(e) => {
+                warn!("couldn't convert row {}", i + 2);
+                errlog::warn_chain(&e);
+                skipped += 1;
+                record.push_field("");
+                record.push_field("");
+            }
+        }
+
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+
+    Ok(skipped)
+}
+
+fn convert_record(record: &StringRecord, value_col: usize, unit_col: usize, to: Unit, mw: Option<f64>) -> Result<f64, Error> {
+    let raw_value = record.get(value_col).unwrap_or("").trim();
+    let raw_unit = record.get(unit_col).unwrap_or("").trim();
+
+    let value = raw_value.parse::<f64>()
+        .context(format!("reading value <{}>", raw_value))?;
+    let unit = crate::parse_unit(raw_unit)?;
+
+    Ok(crate::convert(value, unit, to, mw)
+        .context(format!("converting {} {} to {}", value, unit, to))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+    use crate::Opt;
+
+    fn convert_batch(input: &str, args: &[&str]) -> (usize, Vec<Vec<String>>) {
+        let opts = Opt::from_iter(["unitconv", "--batch", "in.csv"].iter().chain(args));
+        let to = crate::parse_unit(opts.batch_to.as_ref().unwrap()).unwrap();
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        let skipped = convert_records(csv::Reader::from_reader(input.as_bytes()), &mut wtr, to, &opts).unwrap();
+
+        let output = wtr.into_inner().unwrap();
+        let rows = csv::Reader::from_reader(output.as_slice()).records()
+            .map(|r| r.unwrap().iter().map(str::to_string).collect())
+            .collect();
+        (skipped, rows)
+    }
+
+    #[test]
+    fn batch_conversion() {
+        let input = "Sample,Value,Value Unit\nA,1543,mg/dL\nB,2,widgets\nC,3,mL\nD,,g/L\n";
+        let (skipped, rows) = convert_batch(input, &["--to", "g/L"]);
+
+        assert_eq!(skipped, 3);
+        assert_eq!(rows[0], ["A", "1543", "mg/dL", "15.43", "g/L"]);
+        // an unknown unit, units of another type, and a missing value
+        for (row, sample) in rows[1..].iter().zip(&["B", "C", "D"]) {
+            assert_eq!(&row[0], sample);
+            assert_eq!(row[3..], ["", ""]);
+        }
+    }
+
+    #[test]
+    fn batch_columns_and_molecular_weight() {
+        let input = "Conc,Unit\n2,uM\n";
+        let (skipped, rows) = convert_batch(input, &["--to", "ng/mL", "--value-col", "Conc", "--unit-col", "Unit", "--mw", "180.16"]);
+        assert_eq!(skipped, 0);
+        assert_eq!(rows[0][2].parse::<f64>().unwrap().round(), 360.0);
+        assert_eq!(rows[0][3], "ng/mL");
+
+        let opts = Opt::from_iter(&["unitconv", "--batch", "in.csv", "--to", "g/L"]);
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        let err = convert_records(csv::Reader::from_reader(input.as_bytes()), &mut wtr, "g/L".parse().unwrap(), &opts).unwrap_err();
+        assert_eq!(err.to_string(), "missing column <Value>");
+    }
+}
@@ -0,0 +1,155 @@
+mod batch;
+
+use structopt::StructOpt;
+use failure::{Error, ResultExt, bail};
+use log::{debug, info};
+use flexi_logger::{Logger, default_format};
+use std::path::PathBuf;
+use std::io;
+
+use units::Unit;
+
+#[derive(Debug, StructOpt)]
+/// Convert a value between any two units, e.g. `unitconv 1543 mg/dL g/L`, or
+/// convert a column of values in a CSV file with `--batch`
+pub struct Opt {
+    /// Value to convert
+    #[structopt(name = "VALUE")]
+    value: Option<f64>,
+    /// Unit of VALUE
+    #[structopt(name = "FROM")]
+    from: Option<String>,
+    /// Unit to convert VALUE into
+    #[structopt(name = "TO")]
+    to: Option<String>,
+    /// Molecular weight (g/mol) used to convert between molar and mass concentrations
+    #[structopt(long = "mw")]
+    mol_weight: Option<f64>,
+    /// CSV file with a column of values and a column of units to convert into the `--to` unit
+    #[structopt(short = "b", long = "batch", parse(from_os_str))]
+    batch: Option<PathBuf>,
+    /// Unit to convert batch values into
+    #[structopt(short = "t", long = "to")]
+    batch_to: Option<String>,
+    /// Column of values in the batch file
+    #[structopt(long = "value-col", default_value = "Value")]
+    value_col: String,
+    /// Column of units in the batch file
+    #[structopt(long = "unit-col", default_value = "Value Unit")]
+    unit_col: String,
+    /// Output file for batch conversions, or stdout if not present
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// TOML or CSV file of extra unit spellings (alias -> unit) to accept
+    #[structopt(long = "unit-aliases", parse(from_os_str))]
+    unit_aliases: Option<PathBuf>,
+    /// Print every known unit, with its type, aliases, and SI factor, as a table or json and exit
+    #[structopt(long = "list-units", raw(possible_values = r#"&["table", "json"]"#))]
+    list_units: Option<units::CatalogFormat>,
+    /// Print debug info based on the number of "v"s passed
+    #[structopt(short = "v", parse(from_occurrences))]
+    verbose: usize,
+}
+
+fn main() {
+    let opts = Opt::from_args();
+    let log_level = match opts.verbose {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+
+    Logger::with_str(log_level)
+        .format(default_format)
+        .start()
+        .unwrap_or_else(|e| panic!("Logger initialization failed with {}",e) );
+
+    if let Err(e) = run(opts) {
+        errlog::print_chain(&e);
+        ::std::process::exit(1);
+    }
+}
+
+fn run(opts: Opt) -> Result<(), Error> {
+    debug!("{:?}", &opts);
+
+    if let Some(aliases) = opts.unit_aliases.as_ref() {
+        let n = units::load_aliases(aliases)
+            .context(format!("loading unit aliases from <{:?}>", aliases))?;
+        info!("loaded {} unit alias(es) from {:?}", n, aliases);
+    }
+    if let Some(format) = opts.list_units {
+        units::write_catalog(io::stdout(), format).context("printing unit list")?;
+        return Ok(());
+    }
+
+    if let Some(input) = opts.batch.as_ref() {
+        let to = match opts.batch_to.as_ref() {
+            Some(to) => parse_unit(to)?,
+            None => bail!("batch conversion needs a target unit passed with --to"),
+        };
+        return batch::convert_csv(input, to, &opts);
+    }
+
+    match (opts.value, opts.from.as_ref(), opts.to.as_ref()) {
+        (Some(value), Some(from), Some(to)) => {
+            let (from, to) = (parse_unit(from)?, parse_unit(to)?);
+            let converted = convert(value, from, to, opts.mol_weight)
+                .context(format!("converting {} {} to {}", value, from, to))?;
+            println!("{} {}", converted, to);
+            Ok(())
+        },
+        _ => bail!("pass a VALUE, FROM unit, and TO unit, or a --batch file"),
+    }
+}
+
+/// Convert `value`, going through the molecular weight if one was given
+fn convert(value: f64, from: Unit, to: Unit, mw: Option<f64>) -> Result<f64, units::SIError> {
+    match mw {
+        Some(mw) => units::convert_molar((value, from), to, mw),
+        None => units::convert((value, from), to),
+    }
+}
+
+fn parse_unit(unit: &str) -> Result<Unit, Error> {
+    Ok(unit.parse::<Unit>().context(format!("reading unit <{}>", unit))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::double_comparable;
+
+    fn unit(s: &str) -> Unit {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn convert_values() {
+        const TOL: f64 = 1e-9;
+        let converted = convert(1543.0, unit("mg/dL"), unit("g/L"), None).unwrap();
+        assert!(double_comparable(converted, 15.43, TOL), "{}", converted);
+        let converted = convert(1.2, unit("kΩ·cm²"), unit("Ω·cm²"), None).unwrap();
+        assert!(double_comparable(converted, 1200.0, TOL), "{}", converted);
+
+        // molar and mass concentrations only convert through a molecular weight
+        let converted = convert(2.0, unit("uM"), unit("ng/mL"), Some(180.16)).unwrap();
+        assert!(double_comparable(converted, 360.32, TOL), "{}", converted);
+        assert!(convert(2.0, unit("uM"), unit("ng/mL"), None).is_err());
+        assert!(convert(2.0, unit("uM"), unit("ng/mL"), Some(0.0)).is_err());
+        // and a molecular weight doesn't get in the way of other conversions
+        let converted = convert(1543.0, unit("mg/dL"), unit("g/L"), Some(180.16)).unwrap();
+        assert!(double_comparable(converted, 15.43, TOL), "{}", converted);
+    }
+
+    #[test]
+    fn incompatible_units() {
+        let err = convert(1.0, unit("mg/dL"), unit("mL"), None).unwrap_err();
+        assert!(matches!(err, units::SIError::IncompatibleTypes(..)));
+        assert_eq!(err.to_string(), "Cannot convert from concentration to volume");
+
+        let err = convert(1.0, unit("U/L"), unit("ng/mL"), Some(180.16)).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot convert from enzyme activity to concentration");
+    }
+}
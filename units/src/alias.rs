@@ -0,0 +1,222 @@
+//! A registry of alternate spellings for units. Lookups ignore whitespace
+//! and case, and treat the micro sign (U+00B5), Greek mu (U+03BC), and "u"
+//! as the same character. Besides the built-in aliases, more can be kept in a
+//! `UnitAliases` registry and passed to `Unit::parse_with`, or loaded into the
+//! process-wide registry used by `str::parse` with `load_aliases`.
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{RwLock, OnceLock};
+use std::fs;
+use failure::Fail;
+use crate::Unit;
+
+/// Built-in (alias, canonical unit) pairs for spellings the unit grammar can't parse
+static BUILTIN: [(&str, &str); 18] = [
+    ("mcg", "µg"),
+    ("mcg/ml", "µg/mL"),
+    ("mcg/dl", "µg/dL"),
+    ("mcg/l", "µg/L"),
+    ("pm", "pM"),
+    ("nm", "nM"),
+    ("um", "µM"),
+    ("mm", "mM"),
+    ("mg%", "mg/dL"),
+    ("mg/100ml", "mg/dL"),
+    ("cc", "mL"),
+    ("hrs", "hour"),
+    ("ng/day/millioncells", "ng/day/10^6 cells"),
+    ("pg/day/thousandcells", "pg/day/10^3 cells"),
+    ("ohmcm2", "Ω·cm²"),
+    ("ohmcm²", "Ω·cm²"),
+    ("ωcm2", "Ω·cm²"),
+    ("ω·cm2", "Ω·cm²"),
+];
+
+#[derive(Debug, Fail)]
+pub enum AliasError {
+    #[fail(display = "couldn't read unit alias file <{}>", _0)]
+    Io(String, #[cause] std::io::Error),
+    #[fail(display = "couldn't parse TOML unit alias file <{}>", _0)]
+    Toml(String, #[cause] toml::de::Error),
+    #[fail(display = "couldn't parse CSV unit alias file <{}>", _0)]
+    Csv(String, #[cause] csv::Error),
+    #[fail(display = "unit alias file <{}> was not a .toml or .csv file", _0)]
+    UnkFormat(String),
+    #[fail(display = "alias <{}> points to unknown unit <{}>", _0, _1)]
+    UnkUnit(String, String),
+}
+
+/// User-supplied aliases, keyed by their `normalize`d spelling
+#[derive(Debug, Clone, Default)]
+pub struct UnitAliases {
+    aliases: HashMap<String, String>,
+}
+
+impl UnitAliases {
+    pub fn new() -> Self {
+        UnitAliases::default()
+    }
+
+    /// Load aliases from a `.toml` file of `"alias" = "unit"` pairs, or
+    /// a `.csv` file with `Alias` and `Unit` columns
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, AliasError> {
+        let mut aliases = UnitAliases::new();
+        aliases.load(path)?;
+        Ok(aliases)
+    }
+
+    /// Register `alias` as another spelling of `unit`. `unit` must be a parsable unit.
+    pub fn add(&mut self, alias: &str, unit: &str) -> Result<(), AliasError> {
+        if Unit::parse_with(unit, self).is_err() {
+            return Err(AliasError::UnkUnit(alias.to_string(), unit.to_string()));
+        }
+        self.aliases.insert(normalize(alias), unit.to_string());
+
+        Ok(())
+    }
+
+    /// Add the aliases of a `.toml` or `.csv` file, returning the number of aliases added
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, AliasError> {
+        let pairs = read_pairs(path.as_ref())?;
+        for (alias, unit) in pairs.iter() {
+            self.add(alias, unit)?;
+        }
+
+        Ok(pairs.len())
+    }
+
+    /// Look up the canonical spelling for `unit`, checking these aliases before the built-in ones
+    pub fn resolve(&self, unit: &str) -> Option<String> {
+        let key = normalize(unit);
+
+        self.aliases.get(&key).cloned().or_else(|| {
+            BUILTIN.iter()
+                .find(|(alias, _)| *alias == key)
+                .map(|(_, canonical)| canonical.to_string())
+        })
+    }
+
+    /// Every (alias, canonical unit) pair, built-in and user
+    pub fn all(&self) -> Vec<(String, String)> {
+        BUILTIN.iter()
+            .map(|(alias, canonical)| (alias.to_string(), canonical.to_string()))
+            .chain(self.aliases.iter().map(|(a, c)| (a.clone(), c.clone())))
+            .collect()
+    }
+
+    /// The number of user aliases
+    pub fn len(&self) -> usize {
+        self.aliases.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty()
+    }
+}
+
+/// The process-wide aliases used when units are parsed with `str::parse`, which
+/// tools fill from their `--unit-aliases` file at startup
+fn registry() -> &'static RwLock<UnitAliases> {
+    static ALIASES: OnceLock<RwLock<UnitAliases>> = OnceLock::new();
+    ALIASES.get_or_init(|| RwLock::new(UnitAliases::new()))
+}
+
+/// Run `f` with the process-wide aliases
+pub(crate) fn with_registry<T>(f: impl FnOnce(&UnitAliases) -> T) -> T {
+    match registry().read() {
+        Ok(aliases) => f(&aliases),
+        Err(poisoned) => f(&poisoned.into_inner()),
+    }
+}
+
+/// Put a unit string into the form used for alias lookups: no whitespace,
+/// lowercase, and any micro sign written as "u"
+pub fn normalize(unit: &str) -> String {
+    unit.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| if c == 'µ' || c == 'μ' { 'u' } else { c })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Every (alias, canonical unit) pair of the process-wide registry, built-in and user
+pub fn all_aliases() -> Vec<(String, String)> {
+    with_registry(UnitAliases::all)
+}
+
+/// Register `alias` as another spelling of `unit` in the process-wide registry.
+/// `unit` must be a parsable unit.
+pub fn add_alias(alias: &str, unit: &str) -> Result<(), AliasError> {
+    let mut aliases = with_registry(UnitAliases::clone);
+    aliases.add(alias, unit)?;
+    *registry().write().expect("unit alias registry lock was poisoned") = aliases;
+
+    Ok(())
+}
+
+/// Load extra aliases into the process-wide registry from a `.toml` file of
+/// `"alias" = "unit"` pairs, or a `.csv` file with `Alias` and `Unit` columns.
+/// Returns the number of aliases added.
+pub fn load_aliases<P: AsRef<Path>>(path: P) -> Result<usize, AliasError> {
+    let mut aliases = with_registry(UnitAliases::clone);
+    let n = aliases.load(path)?;
+    *registry().write().expect("unit alias registry lock was poisoned") = aliases;
+
+    Ok(n)
+}
+
+/// Read the (alias, unit) pairs of a `.toml` or `.csv` alias file
+fn read_pairs(path: &Path) -> Result<Vec<(String, String)>, AliasError> {
+    let name = path.display().to_string();
+    let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+
+    match ext.as_deref() {
+        Some("toml") => {
+            let contents = fs::read_to_string(path)
+                .map_err(|e| AliasError::Io(name.clone(), e))?;
+            let table: HashMap<String, String> = toml::from_str(&contents)
+                .map_err(|e| AliasError::Toml(name.clone(), e))?;
+            Ok(table.into_iter().collect())
+        },
+        Some("csv") => {
+            let mut rdr = csv::Reader::from_path(path)
+                .map_err(|e| AliasError::Csv(name.clone(), e))?;
+            rdr.deserialize()
+                .collect::<Result<_, _>>()
+                .map_err(|e| AliasError::Csv(name.clone(), e))
+        },
+        _ => Err(AliasError::UnkFormat(name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized_keys() {
+        assert_eq!(normalize("ng / mL"), "ng/ml");
+        assert_eq!(normalize("NG/ML"), "ng/ml");
+        assert_eq!(normalize("μg/mL"), "ug/ml");
+        assert_eq!(normalize("µg/mL"), "ug/ml");
+    }
+
+    #[test]
+    fn builtin_and_user_aliases() {
+        // a registry of its own, so that units parsed by other tests aren't affected
+        let mut aliases = UnitAliases::new();
+        assert_eq!(aliases.resolve("MCG/mL").as_deref(), Some("µg/mL"));
+        assert_eq!(aliases.resolve("uM").as_deref(), Some("µM"));
+        assert_eq!(aliases.resolve("micrograms per mL"), None);
+
+        aliases.add("micrograms per mL", "µg/mL").unwrap();
+        assert_eq!(aliases.resolve("Micrograms Per mL").as_deref(), Some("µg/mL"));
+        assert_eq!(Unit::parse_with("Micrograms Per mL", &aliases).unwrap(), "µg/mL".parse::<Unit>().unwrap());
+        assert!(aliases.add("widgets", "widget/mL").is_err());
+        assert_eq!(aliases.len(), 1);
+
+        // only the registry the alias was added to knows it
+        assert!("micrograms per mL".parse::<Unit>().is_err());
+        assert!(Unit::parse_with("micrograms per mL", &UnitAliases::new()).is_err());
+    }
+}
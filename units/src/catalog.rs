@@ -0,0 +1,147 @@
+//! A listing of every named unit, with its type, spellings, and SI factor,
+//! that can be printed as a table or as JSON
+use std::io::{self, Write};
+use std::str::FromStr;
+use serde_derive::Serialize;
+use crate::{alias, SIUnit, Unit, UnitType};
+
+/// Info about one named unit
+#[derive(Debug, Clone, Serialize)]
+pub struct UnitInfo {
+    pub unit: SIUnit,
+    #[serde(rename = "type")]
+    pub unit_type: UnitType,
+    pub aliases: Vec<String>,
+    pub si_factor: f64,
+    pub si_base: Unit,
+}
+
+impl From<SIUnit> for UnitInfo {
+    fn from(unit: SIUnit) -> Self {
+        let spellings = unit.aliases();
+        let mut aliases: Vec<String> = spellings.iter()
+            .map(|a| a.to_string())
+            .collect();
+        // registered aliases that point to this unit's exact spelling or parts
+        aliases.extend(alias::all_aliases()
+            .into_iter()
+            .filter(|(_, canonical)| {
+                canonical == unit.as_str()
+                    || spellings.contains(&canonical.as_str())
+                    || canonical.parse::<Unit>().map(|u| u == unit.unit()).unwrap_or(false)
+            })
+            .map(|(alias, _)| alias)
+        );
+
+        UnitInfo {
+            unit,
+            unit_type: unit.unit_type(),
+            aliases,
+            si_factor: unit.si_factor(),
+            si_base: unit.si_base(),
+        }
+    }
+}
+
+/// Iterate over info for every named unit
+pub fn catalog() -> impl Iterator<Item = UnitInfo> {
+    SIUnit::all().map(UnitInfo::from)
+}
+
+/// How to print the unit catalog
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CatalogFormat {
+    Table,
+    Json,
+}
+
+impl FromStr for CatalogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(CatalogFormat::Table),
+            "json" => Ok(CatalogFormat::Json),
+            _ => Err(format!("unknown unit list format <{}>; expected table or json", s)),
+        }
+    }
+}
+
+/// Write the unit catalog to `wtr` in the given format
+pub fn write_catalog<W: Write>(mut wtr: W, format: CatalogFormat) -> io::Result<()> {
+    let units: Vec<UnitInfo> = catalog().collect();
+
+    match format {
+        CatalogFormat::Json => {
+            serde_json::to_writer_pretty(&mut wtr, &units)?;
+            writeln!(wtr)
+        },
+        CatalogFormat::Table => {
+            let header = ["Unit", "Type", "SI Factor", "SI Base", "Aliases"].iter()
+                .map(|h| h.to_string())
+                .collect::<Vec<_>>();
+            let rows: Vec<Vec<String>> = units.iter()
+                .map(|info| vec![
+                    info.unit.to_string(),
+                    info.unit_type.to_string(),
+                    format!("{:e}", info.si_factor),
+                    info.si_base.to_string(),
+                    info.aliases.join(", "),
+                ])
+                .collect();
+            let widths: Vec<usize> = (0..header.len())
+                .map(|col| {
+                    rows.iter()
+                        .chain(Some(&header))
+                        .map(|r| r[col].chars().count())
+                        .max()
+                        .unwrap_or(0)
+                })
+                .collect();
+
+            let write_row = |wtr: &mut W, row: &[String]| -> io::Result<()> {
+                for (cell, width) in row.iter().zip(widths.iter()) {
+                    write!(wtr, "| {:<w$} ", cell, w = width)?;
+                }
+                writeln!(wtr, "|")
+            };
+            let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+
+            write_row(&mut wtr, &header)?;
+            write_row(&mut wtr, &rule)?;
+            for row in rows.iter() {
+                write_row(&mut wtr, row)?;
+            }
+
+            Ok(())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_unit_is_listed() {
+        let listed: Vec<UnitInfo> = catalog().collect();
+
+        assert_eq!(listed.len(), SIUnit::all().count());
+        let ul = listed.iter().find(|i| i.unit == SIUnit::ul).unwrap();
+        assert_eq!(ul.unit_type, UnitType::Volume);
+        assert_eq!(ul.si_base.to_string(), "L");
+        assert!(ul.aliases.iter().any(|a| a == "uL"));
+    }
+
+    #[test]
+    fn json_catalog() {
+        let mut out = Vec::new();
+        write_catalog(&mut out, CatalogFormat::Json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+        let first = &json[0];
+        assert_eq!(first["unit"], "pg/mL");
+        assert_eq!(first["type"], "concentration");
+        assert_eq!(first["si_base"], "g/L");
+    }
+}
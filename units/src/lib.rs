@@ -1,15 +1,31 @@
 use std::fmt;
 use std::str::FromStr;
+use std::ops::Mul;
 use serde::de::{self, Visitor, Deserialize, Deserializer};
 use serde::ser::{Serialize, Serializer};
 use failure::Fail;
 
+mod unit;
+mod alias;
+mod catalog;
+mod uncertain;
+
+pub use crate::unit::Unit as Unit;
+pub use crate::unit::Dimension as Dimension;
+pub use crate::alias::AliasError as AliasError;
+pub use crate::alias::UnitAliases as UnitAliases;
+pub use crate::alias::{add_alias, load_aliases};
+pub use crate::catalog::{catalog, write_catalog, CatalogFormat, UnitInfo};
+pub use crate::uncertain::Uncertain as Uncertain;
+
 #[derive(Debug, Fail)]
 pub enum SIError {
-    #[fail(display = "Cannot convert from {:?} to {:?}", _0, _1)]
+    #[fail(display = "Cannot convert from {} to {}", _0, _1)]
     IncompatibleTypes(UnitType, UnitType),
     #[fail(display = "Unknown SI unit <{}>", _0)]
     UnkType(String),
+    #[fail(display = "Molecular weight must be a positive number of g/mol, not <{}>", _0)]
+    InvalidMolecularWeight(f64),
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -31,54 +47,140 @@ pub enum SIUnit {
 
     umol_l,
 
+    sec,
+    min,
+    hour,
+    day,
+
     g_day,
     ng_day,
 
     g_day_cell,
     ng_day_cell,
     ng_day_millioncells,
+    pg_day_thousandcells,
+    pg_day_millioncells,
+    ug_day_millioncells,
+
+    u_l,
+    mu_ml,
+    iu_l,
+
+    cells_ml,
+
+    percent,
+    fraction,
+    fold_change,
+
+    ohm_cm2,
 }
 
 impl SIUnit {
-    /// Attempt to standardize an input `&str`. None is returned if 
-    /// the input could not be standardized.
-    pub fn standardize(input: &str) -> Option<&'static str> {
-        input.parse::<Self>().ok().map(|unit| unit.as_str())
+    const ALL: [SIUnit; 32] = {
+        use self::SIUnit::*;
+        [
+            pg_ml, ng_ml, mg_ml, mg_dl, g_l,
+            ml, ul, dl, l,
+            ng, g,
+            umol_l,
+            sec, min, hour, day,
+            g_day, ng_day,
+            g_day_cell, ng_day_cell, ng_day_millioncells,
+            pg_day_thousandcells, pg_day_millioncells, ug_day_millioncells,
+            u_l, mu_ml, iu_l,
+            cells_ml,
+            percent, fraction, fold_change,
+            ohm_cm2,
+        ]
+    };
+
+    /// Attempt to standardize an input `&str`, going through the unit alias 
+    /// registry if needed. None is returned if the input could not be standardized.
+    pub fn standardize(input: &str) -> Option<String> {
+        input.parse::<Self>()
+            .map(|unit| unit.as_str().to_string())
+            .or_else(|_| input.parse::<Unit>().map(|unit| unit.to_string()))
+            .ok()
     }
 
-    fn unit_type(&self) -> UnitType {
-        use self::SIUnit::*;
-        use self::UnitType::*;
-        match self {
-            pg_ml | ng_ml | mg_ml | mg_dl | g_l
-                => Concentration,
-            ul | ml | dl | l 
-                => Volume,
-            g | ng
-                => Mass,
-            umol_l
-                => Molarity,
-            ng_day | g_day
-                => Rate,
-            ng_day_cell | ng_day_millioncells | g_day_cell
-                => CellNormalized,
-        }
+    /// The compositional `Unit` for this named unit
+    pub fn unit(&self) -> Unit {
+        self.as_str()
+            .parse()
+            .expect("SIUnit spellings are valid unit strings")
+    }
+
+    /// Every named unit
+    pub fn all() -> impl Iterator<Item = SIUnit> {
+        SIUnit::ALL.iter().cloned()
     }
-    /*
-    fn si_base(&self) -> Self {
+
+    /// The type of quantity this unit measures
+    pub fn unit_type(&self) -> UnitType {
+        self.unit().unit_type()
+    }
+
+    /// Factor to put this unit into base SI unit
+    pub fn si_factor(&self) -> f64 {
+        self.unit().si_factor()
+    }
+
+    /// The base SI unit of the same dimension, e.g. g/L for ng/mL
+    pub fn si_base(&self) -> Unit {
+        self.unit()
+            .dimension()
+            .si_base()
+            .expect("named units have at most two quantities in their numerator")
+    }
+
+    /// Other exact spellings of this unit that are accepted when parsing
+    pub fn aliases(&self) -> &'static [&'static str] {
         use self::SIUnit::*;
-        use self::UnitType::*;
 
-        match self.unit_type() {
-            Concentration => g_l,
-            Volume => l,
-            Rate => g_day,
-            CellNormalized => g_day_cell,
-            Mass => g,
+        match self {
+            pg_ml => &["pg/ml"],
+            ng_ml => &["ng/ml"],
+            mg_ml => &["mg/ml"],
+            mg_dl => &["mg/dl"],
+            g_l => &["g/l"],
+
+            ml => &["ml"],
+            ul => &["µl", "ul", "uL"],
+            dl => &["dl"],
+            l => &["l"],
+
+            g | ng => &[],
+
+            umol_l => &["umol/L", "umol/l", "µmol/l", "µM", "uM"],
+
+            sec => &["sec"],
+            min => &["minute", "minutes"],
+            hour => &["h", "hr", "hours"],
+            day => &["d", "days"],
+
+            g_day | ng_day | g_day_cell | ng_day_cell => &[],
+
+            ng_day_millioncells => &["ng/day/10^6cells"],
+            pg_day_thousandcells => &["pg/day/10^3cells"],
+            pg_day_millioncells => &["pg/day/10^6cells"],
+            ug_day_millioncells => &["µg/day/10^6cells", "ug/day/10^6 cells", "ug/day/10^6cells"],
+
+            u_l => &["U/l", "u/l"],
+            mu_ml => &["mU/ml", "mu/ml"],
+            iu_l => &["IU/l", "iu/l"],
+
+            cells_ml => &["cells/ml", "cell/mL"],
+
+            percent => &["percent"],
+            fraction => &[],
+            fold_change => &["fold", "fold-change"],
+
+            ohm_cm2 => &["Ω·cm2", "ohm·cm²", "ohm*cm2", "Ohm*cm^2"],
         }
-    }*/
+    }
 
-    fn as_str(&self) -> &'static str {
+    /// The canonical spelling of this unit
+    pub fn as_str(&self) -> &'static str {
         use self::SIUnit::*;
 
         match self {
@@ -98,41 +200,32 @@ impl SIUnit {
 
             umol_l => "µmol/L",
 
+            sec => "s",
+            min => "min",
+            hour => "hour",
+            day => "day",
+
             g_day => "g/day",
             ng_day => "ng/day",
 
             g_day_cell => "g/day/cell",
             ng_day_cell => "ng/day/cell",
             ng_day_millioncells => "ng/day/10^6 cells",
-        }
-    }
-    /// Factor to put this unit into base SI unit
-    fn si_factor(&self) -> f64 {
-        use self::SIUnit::*;
+            pg_day_thousandcells => "pg/day/10^3 cells",
+            pg_day_millioncells => "pg/day/10^6 cells",
+            ug_day_millioncells => "µg/day/10^6 cells",
 
-        match self {
-            pg_ml => 1e-9,
-            ng_ml => 1e-6,
-            mg_ml => 1.0,
-            mg_dl => 1e-2,
-            g_l => 1.0,
-
-            ml => 1e-3,
-            ul => 1e-6,
-            dl => 1e-1,
-            l => 1.0,
-
-            g => 1.0,
-            ng => 1e-9,
-
-            umol_l => 1e-6,
-
-            g_day => 1.0,
-            ng_day => 1e-9,
-            /* These seem off... */
-            g_day_cell => 1.0,
-            ng_day_cell => 1e-9,
-            ng_day_millioncells => 1.0 / (1_000_000_000.0 * 1_000_000_000.0),
+            u_l => "U/L",
+            mu_ml => "mU/mL",
+            iu_l => "IU/L",
+
+            cells_ml => "cells/mL",
+
+            percent => "%",
+            fraction => "fraction",
+            fold_change => "fold change",
+
+            ohm_cm2 => "Ω·cm²",
         }
     }
 }
@@ -147,34 +240,18 @@ impl FromStr for SIUnit {
     type Err = SIError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        use self::SIUnit::*;
-
-        match s {
-            "pg/mL" | "pg/ml" => Ok(pg_ml),
-            "ng/mL" | "ng/ml" => Ok(ng_ml),
-            "mg/mL" | "mg/ml" => Ok(mg_ml),
-            "mg/dL" | "mg/dl" => Ok(mg_dl),
-            "g/L" | "g/l" => Ok(g_l),
-
-            "mL" | "ml" => Ok(ml),
-            "µL" | "µl" | "ul" | "uL" => Ok(ul),
-            "dL" | "dl" => Ok(dl),
-            "L" | "l" => Ok(l),
-
-            "g" => Ok(g),
-            "ng" => Ok(ng),
-
-            "umol/L" | "umol/l" | "µmol/L" | "µmol/l" | "µM" | "uM" => Ok(umol_l),
-
-            "g/day" => Ok(g_day),
-            "ng/day" => Ok(ng_day),
-
-            "g/day/cell" => Ok(g_day_cell),
-            "ng/day/cell" => Ok(ng_day_cell),
-            "ng/day/10^6 cells" | "ng/day/10^6cells" => Ok(ng_day_millioncells),
-            
-            _ => Err(SIError::UnkType(s.to_string())),
-        }
+        let named = || SIUnit::all();
+        let spelled = |s: &str| named().find(|n| n.as_str() == s || n.aliases().contains(&s));
+
+        spelled(s)
+            // a registered alias of a named unit's spelling, e.g. "UM" for µM
+            .or_else(|| spelled(&alias::with_registry(|aliases| aliases.resolve(s))?))
+            // any other spelling of a named unit, but not a different unit of the same size
+            .or_else(|| {
+                let unit = s.parse::<Unit>().ok()?;
+                named().find(|n| n.unit() == unit)
+            })
+            .ok_or_else(|| SIError::UnkType(s.to_string()))
     }
 }
 
@@ -199,7 +276,7 @@ impl<'de> Visitor<'de> for SIUnitVisitor {
     type Value = SIUnit;
 
     fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "A typical SI concentration unit")
+        write!(f, "A typical SI concentration, activity, count, or ratio unit")
     }
 
     fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
@@ -210,6 +287,12 @@ impl<'de> Visitor<'de> for SIUnitVisitor {
     }
 }
 
+impl From<SIUnit> for Unit {
+    fn from(u: SIUnit) -> Self {
+        u.unit()
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum UnitType {
     Concentration,
@@ -218,9 +301,95 @@ pub enum UnitType {
     Rate,
     Mass,
     Molarity,
+    Time,
+    /// Enzyme activity per volume, e.g. U/L
+    EnzymeActivity,
+    /// Cells per volume
+    CellDensity,
+    /// A ratio, e.g. percent or fraction
+    Dimensionless,
+    /// A fold change, which is a ratio too, but not a percent or fraction
+    FoldChange,
+    /// Resistance times area, as in TEER
+    AreaResistance,
+    /// Any other combination of base quantities
+    Other(Dimension),
 }
 
-pub fn convert((val, unit): (f64, SIUnit), to: SIUnit) -> Result<f64, SIError> {
+impl UnitType {
+    /// Check if values of this type measure an amount of a substance, so that they
+    /// can be normalized into a mass secretion rate
+    pub fn is_mass_based(&self) -> bool {
+        use self::UnitType::*;
+
+        matches!(self, Concentration | Molarity | Mass | Rate | CellNormalized)
+    }
+}
+
+impl fmt::Display for UnitType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::UnitType::*;
+
+        match self {
+            Concentration => write!(f, "concentration"),
+            Volume => write!(f, "volume"),
+            CellNormalized => write!(f, "cell normalized rate"),
+            Rate => write!(f, "rate"),
+            Mass => write!(f, "mass"),
+            Molarity => write!(f, "molarity"),
+            Time => write!(f, "time"),
+            EnzymeActivity => write!(f, "enzyme activity"),
+            CellDensity => write!(f, "cell density"),
+            Dimensionless => write!(f, "dimensionless ratio"),
+            FoldChange => write!(f, "fold change"),
+            AreaResistance => write!(f, "area resistance"),
+            Other(d) => write!(f, "{}", d),
+        }
+    }
+}
+
+impl Serialize for UnitType {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+    {
+        s.collect_str(self)
+    }
+}
+
+impl From<Dimension> for UnitType {
+    fn from(d: Dimension) -> Self {
+        use self::UnitType::*;
+
+        let none = Dimension::default();
+        let types = [
+            (Dimension { mass: 1, volume: -1, ..none }, Concentration),
+            (Dimension { volume: 1, ..none }, Volume),
+            (Dimension { mass: 1, time: -1, cells: -1, ..none }, CellNormalized),
+            (Dimension { mass: 1, time: -1, ..none }, Rate),
+            (Dimension { mass: 1, ..none }, Mass),
+            (Dimension { amount: 1, volume: -1, ..none }, Molarity),
+            (Dimension { time: 1, ..none }, Time),
+            (Dimension { activity: 1, volume: -1, ..none }, EnzymeActivity),
+            (Dimension { cells: 1, volume: -1, ..none }, CellDensity),
+            (none, Dimensionless),
+            (Dimension { fold: 1, ..none }, FoldChange),
+            (Dimension { resistance: 1, length: 2, ..none }, AreaResistance),
+        ];
+
+        types.iter()
+            .find(|(dim, _)| *dim == d)
+            .map_or(Other(d), |(_, t)| *t)
+    }
+}
+
+/// Convert a value between two units of the same type. The value can be
+/// an `f64`, or an `Uncertain` to carry its uncertainty through the conversion.
+pub fn convert<V, F, T>((val, unit): (V, F), to: T) -> Result<V, SIError> 
+where V: Mul<f64, Output = V>,
+      F: Into<Unit>,
+      T: Into<Unit>,
+{
+    let (unit, to) = (unit.into(), to.into());
     let from_type = unit.unit_type();
     let to_type = to.unit_type();
     if from_type != to_type {
@@ -232,6 +401,36 @@ pub fn convert((val, unit): (f64, SIUnit), to: SIUnit) -> Result<f64, SIError> {
     Ok(val * (from_fact * to_fact))
 }
 
+/// Convert a value between two units, using the molecular weight `mw` (in g/mol) 
+/// to go between a molar (`UnitType::Molarity`) and a mass (`UnitType::Concentration`)
+/// concentration. Units of the same type are converted as in `convert`.
+pub fn convert_molar<V, F, T>((val, unit): (V, F), to: T, mw: f64) -> Result<V, SIError> 
+where V: Mul<f64, Output = V>,
+      F: Into<Unit>,
+      T: Into<Unit>,
+{
+    use self::UnitType::*;
+
+    let (unit, to) = (unit.into(), to.into());
+    if !mw.is_finite() || mw <= 0.0 {
+        return Err(SIError::InvalidMolecularWeight(mw));
+    }
+
+    match (unit.unit_type(), to.unit_type()) {
+        // mol/L * g/mol = g/L
+        (Molarity, Concentration) => {
+            let mol_l = val * unit.si_factor();
+            convert((mol_l * mw, SIUnit::g_l), to)
+        },
+        // g/L / (g/mol) = mol/L
+        (Concentration, Molarity) => {
+            let g_l = val * unit.si_factor();
+            Ok(g_l * (mw.recip() * to.si_factor().recip()))
+        },
+        _ => convert((val, unit), to),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -268,6 +467,186 @@ mod tests {
         assert!(double_comparable(convert((32.0, mg_ml), g_l).unwrap(), 32.0, TOL), "32 mg_ml to g_l");
         assert!(double_comparable(convert((1.0, mg_dl), g_l).unwrap(), 1e-2, TOL), "1 mg_dl to g_l");
     }
-    // TODO: molarity conversion
-}
 
+    #[test]
+    fn time_conversion() {
+        use self::SIUnit::*;
+
+        assert!(double_comparable(convert((36.0, hour), day).unwrap(), 1.5, TOL), "36 hour to day");
+        assert!(double_comparable(convert((90.0, min), hour).unwrap(), 1.5, TOL), "90 min to hour");
+        assert!(double_comparable(convert((2.0, day), sec).unwrap(), 172_800.0, TOL), "2 day to s");
+        assert!(double_comparable(convert((30.0, sec), min).unwrap(), 0.5, TOL), "30 s to min");
+    }
+
+    #[test]
+    fn rate_conversion() {
+        use self::SIUnit::*;
+        let ng_hour: Unit = "ng/hour".parse().unwrap();
+        let ug_min_millioncells: Unit = "µg/min/10^6 cells".parse().unwrap();
+        let pg_s_cell: Unit = "pg/s/cell".parse().unwrap();
+
+        assert!(double_comparable(convert((1.0, ng_hour), ng_day).unwrap(), 24.0, TOL), "1 ng/hour to ng/day");
+        assert!(double_comparable(convert((48.0, ng_day), ng_hour).unwrap(), 2.0, TOL), "48 ng/day to ng/hour");
+        assert!(double_comparable(convert((1.0, ug_min_millioncells), ng_day_millioncells).unwrap(), 1.44e6, TOL), "1 ug/min/10^6 cells to ng/day/10^6 cells");
+        assert!(double_comparable(convert((1.0, pg_s_cell), ng_day_cell).unwrap(), 86.4, TOL), "1 pg/s/cell to ng/day/cell");
+        assert!(convert((1.0, ng_hour), ng_day_cell).is_err(), "ng/hour to ng/day/cell");
+        assert!(convert((1.0, hour), ng_day).is_err(), "hour to ng/day");
+    }
+
+    #[test]
+    fn cell_normalized_conversion() {
+        use self::SIUnit::*;
+
+        assert!(double_comparable(convert((1.0, ng_day_cell), ng_day_millioncells).unwrap(), 1e6, TOL), "1 ng/day/cell to ng/day/10^6 cells");
+        assert!(double_comparable(convert((1.0, g_day_cell), ng_day_cell).unwrap(), 1e9, TOL), "1 g/day/cell to ng/day/cell");
+        assert!(double_comparable(convert((1.0, pg_day_thousandcells), ng_day_millioncells).unwrap(), 1.0, TOL), "1 pg/day/10^3 cells to ng/day/10^6 cells");
+        assert!(double_comparable(convert((1.0, pg_day_millioncells), ng_day_millioncells).unwrap(), 1e-3, TOL), "1 pg/day/10^6 cells to ng/day/10^6 cells");
+        assert!(double_comparable(convert((1.0, ug_day_millioncells), ng_day_millioncells).unwrap(), 1e3, TOL), "1 ug/day/10^6 cells to ng/day/10^6 cells");
+        assert!(double_comparable(convert((5.0, ng_day_millioncells), ng_day_cell).unwrap(), 5e-6, TOL), "5 ng/day/10^6 cells to ng/day/cell");
+    }
+
+    /// Converting A -> B -> A is the identity for every pair of compatible units,
+    /// A -> B -> C matches A -> C, and every incompatible pair is an error
+    #[test]
+    fn round_trip_matrix() {
+        const VALUES: [f64; 4] = [1.0, 0.00465, 153.914, 2.1931516981e10];
+
+        for &a in SIUnit::ALL.iter() {
+            for &b in SIUnit::ALL.iter() {
+                let compatible = a.unit().dimension() == b.unit().dimension();
+
+                for &v in VALUES.iter() {
+                    match convert((v, a), b) {
+                        Ok(there) => {
+                            assert!(compatible, "{} to {} should not convert", a, b);
+                            let back = convert((there, b), a).unwrap();
+                            assert!(double_comparable(back, v, TOL), "{} {} to {} and back: {}", v, a, b, back);
+
+                            for &c in SIUnit::ALL.iter().filter(|c| c.unit().dimension() == b.unit().dimension()) {
+                                let chained = convert((there, b), c).unwrap();
+                                let direct = convert((v, a), c).unwrap();
+                                assert!(double_comparable(chained, direct, TOL), "{} {} to {} to {}", v, a, b, c);
+                            }
+                        },
+                        Err(SIError::IncompatibleTypes(..)) => assert!(!compatible, "{} to {} should convert", a, b),
+                        Err(e) => panic!("unexpected error converting {} to {}: {}", a, b, e),
+                    }
+                }
+            }
+        }
+
+        // percent and fraction are the same kind of ratio, but a fold change isn't either one
+        assert!(double_comparable(convert((45.0, SIUnit::percent), SIUnit::fraction).unwrap(), 0.45, TOL));
+        for &ratio in [SIUnit::percent, SIUnit::fraction].iter() {
+            for &(a, b) in [(SIUnit::fold_change, ratio), (ratio, SIUnit::fold_change)].iter() {
+                match convert((2.5, a), b) {
+                    Err(SIError::IncompatibleTypes(..)) => (),
+                    other => panic!("{} to {} should not convert: {:?}", a, b, other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn activity_count_and_ratio_conversion() {
+        use self::SIUnit::*;
+        let iu_ml: Unit = "IU/mL".parse().unwrap();
+        let cells_ul: Unit = "10^3 cells/µL".parse().unwrap();
+        let kohm_cm2: Unit = "kΩ·cm²".parse().unwrap();
+        let ohm_m2: Unit = "Ω·m²".parse().unwrap();
+
+        assert!(double_comparable(convert((150.0, u_l), mu_ml).unwrap(), 150.0, TOL), "150 U/L to mU/mL");
+        assert!(double_comparable(convert((1.0, iu_ml), u_l).unwrap(), 1e3, TOL), "1 IU/mL to U/L");
+        assert!(double_comparable(convert((2.0, cells_ul), cells_ml).unwrap(), 2e6, TOL), "2 10^3 cells/uL to cells/mL");
+        assert!(double_comparable(convert((45.0, percent), fraction).unwrap(), 0.45, TOL), "45 % to fraction");
+        assert!(double_comparable(convert((1.2, kohm_cm2), ohm_cm2).unwrap(), 1200.0, TOL), "1.2 kOhm*cm2 to Ohm*cm2");
+        assert!(double_comparable(convert((1e4, ohm_cm2), ohm_m2).unwrap(), 1.0, TOL), "10^4 Ohm*cm2 to Ohm*m2");
+
+        for &(a, b) in [(u_l, ng_ml), (u_l, umol_l), (cells_ml, ng_ml), (percent, ng_ml), (ohm_cm2, percent), (fold_change, percent)].iter() {
+            match convert((1.0, a), b) {
+                Err(SIError::IncompatibleTypes(..)) => (),
+                other => panic!("{} to {} should not convert: {:?}", a, b, other),
+            }
+        }
+        assert!(!u_l.unit_type().is_mass_based());
+        assert!(!percent.unit_type().is_mass_based());
+        assert!(umol_l.unit_type().is_mass_based());
+    }
+
+    #[test]
+    fn molarity_conversion() {
+        use self::SIUnit::*;
+        // caffeine, 194.19 g/mol
+        const MW: f64 = 194.19;
+
+        assert!(double_comparable(convert_molar((1.0, umol_l), ng_ml, MW).unwrap(), 194.19, TOL), "1 umol_l to ng_ml");
+        assert!(double_comparable(convert_molar((194.19, ng_ml), umol_l, MW).unwrap(), 1.0, TOL), "194.19 ng_ml to umol_l");
+        assert!(double_comparable(convert_molar((2.5, umol_l), g_l, MW).unwrap(), 4.85475e-4, TOL), "2.5 umol_l to g_l");
+        assert!(double_comparable(convert_molar((1.0, mg_dl), umol_l, MW).unwrap(), 51.49595756733096, TOL), "1 mg_dl to umol_l");
+        assert!(double_comparable(convert_molar((5.0, umol_l), umol_l, MW).unwrap(), 5.0, TOL), "5 umol_l to umol_l");
+        assert!(double_comparable(convert_molar((20.0, ng_ml), pg_ml, MW).unwrap(), 20e3, TOL), "20 ng_ml to pg_ml");
+    }
+
+    #[test]
+    fn molarity_conversion_errors() {
+        use self::SIUnit::*;
+
+        assert!(convert((1.0, umol_l), ng_ml).is_err(), "umol_l to ng_ml without a molecular weight");
+        assert!(convert_molar((1.0, umol_l), ng_ml, 0.0).is_err(), "zero molecular weight");
+        assert!(convert_molar((1.0, umol_l), ng_ml, -10.0).is_err(), "negative molecular weight");
+        assert!(convert_molar((1.0, umol_l), ml, 194.19).is_err(), "umol_l to ml");
+    }
+
+    #[test]
+    fn uncertain_conversion() {
+        use self::SIUnit::*;
+
+        let conc = Uncertain::new(2.0, Some(0.5));
+        let converted = convert((conc, ng_ml), pg_ml).unwrap();
+        assert!(double_comparable(converted.value, 2e3, TOL), "2 ng/mL to pg/mL");
+        assert!(double_comparable(converted.uncertainty.unwrap(), 500.0, TOL), "0.5 ng/mL SD to pg/mL");
+
+        let molar = convert_molar((Uncertain::new(1.0, Some(0.1)), umol_l), ng_ml, 194.19).unwrap();
+        assert!(double_comparable(molar.value, 194.19, TOL), "1 umol/L to ng/mL");
+        assert!(double_comparable(molar.uncertainty.unwrap(), 19.419, TOL), "0.1 umol/L SD to ng/mL");
+        assert_eq!(convert((Uncertain::exact(1.0), ml), ul).unwrap().uncertainty, None);
+    }
+
+    #[test]
+    fn named_units_are_compositional() {
+        for unit in SIUnit::ALL.iter() {
+            assert_eq!(unit.unit().to_string(), unit.to_string(), "{:?} spelling", unit);
+            assert_eq!(unit.to_string().parse::<SIUnit>().unwrap(), *unit, "{:?} parsing", unit);
+        }
+    }
+
+    #[test]
+    fn aliased_spellings() {
+        use self::SIUnit::*;
+
+        assert_eq!("NG/ML".parse::<SIUnit>().unwrap(), ng_ml);
+        assert_eq!("ng / mL".parse::<SIUnit>().unwrap(), ng_ml);
+        assert_eq!("μL".parse::<SIUnit>().unwrap(), ul);
+        assert_eq!("UM".parse::<SIUnit>().unwrap(), umol_l);
+        assert_eq!("ug/day/million cells".parse::<SIUnit>().unwrap(), ug_day_millioncells);
+        assert_eq!(SIUnit::standardize("mcg/mL").as_deref(), Some("µg/mL"));
+        assert_eq!(SIUnit::standardize("uM").as_deref(), Some("µmol/L"));
+        assert_eq!(SIUnit::standardize("widgets"), None);
+        // the same size as ng/mL, but entered as µg/L
+        assert!("µg/L".parse::<SIUnit>().is_err());
+        assert_eq!(SIUnit::standardize("ug/L").as_deref(), Some("µg/L"));
+    }
+
+    #[test]
+    fn compositional_conversion() {
+        let ug_ml: Unit = "µg/mL".parse().unwrap();
+        let ng_ul: Unit = "ng/µL".parse().unwrap();
+        let nmol_l: Unit = "nmol/L".parse().unwrap();
+
+        assert!(double_comparable(convert((1.0, ug_ml), SIUnit::ng_ml).unwrap(), 1e3, TOL), "1 ug/mL to ng/mL");
+        assert!(double_comparable(convert((1.0, ng_ul), ug_ml).unwrap(), 1.0, TOL), "1 ng/uL to ug/mL");
+        assert!(double_comparable(convert((250.0, nmol_l), SIUnit::umol_l).unwrap(), 0.25, TOL), "250 nmol/L to umol/L");
+        assert!(double_comparable(convert((1.0, SIUnit::ng_day_cell), SIUnit::ng_day_millioncells).unwrap(), 1e6, TOL), "1 ng/day/cell to ng/day/10^6 cells");
+        assert!(convert((1.0, ug_ml), nmol_l).is_err(), "ug/mL to nmol/L");
+    }
+}
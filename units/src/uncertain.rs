@@ -0,0 +1,163 @@
+//! A value with an optional absolute uncertainty (e.g., an SD or SEM), that
+//! propagates that uncertainty through unit conversions and through differences,
+//! products, and quotients with other uncertain values.
+use std::fmt;
+use std::ops::{Mul, Div, Sub};
+
+/// A value and its absolute uncertainty, in the same unit as the value.
+/// A missing uncertainty is treated as an exact value.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Uncertain {
+    pub value: f64,
+    pub uncertainty: Option<f64>,
+}
+
+impl Uncertain {
+    pub fn new(value: f64, uncertainty: Option<f64>) -> Self {
+        Uncertain { value, uncertainty }
+    }
+
+    /// A value without any uncertainty
+    pub fn exact(value: f64) -> Self {
+        Uncertain { value, uncertainty: None }
+    }
+
+    /// Uncertainty relative to the size of the value
+    pub fn relative(&self) -> Option<f64> {
+        self.uncertainty.map(|u| (u / self.value).abs())
+    }
+
+    /// Combine the absolute uncertainties of two values, each scaled by the partial
+    /// derivative of the result with respect to that value, assuming the errors are
+    /// independent. Absolute errors are used so that a value of 0 doesn't make the
+    /// result NaN, as it would through a relative error.
+    fn combined(&self, other: &Self, d_self: f64, d_other: f64) -> Option<f64> {
+        match (self.uncertainty, other.uncertainty) {
+            (None, None) => None,
+            (a, b) => Some((d_self * a.unwrap_or(0.0)).hypot(d_other * b.unwrap_or(0.0))),
+        }
+    }
+}
+
+impl From<f64> for Uncertain {
+    fn from(value: f64) -> Self {
+        Uncertain::exact(value)
+    }
+}
+
+/// Scaling by an exact factor, as in a unit conversion, scales the uncertainty too
+impl Mul<f64> for Uncertain {
+    type Output = Self;
+
+    fn mul(self, factor: f64) -> Self {
+        Uncertain {
+            value: self.value * factor,
+            uncertainty: self.uncertainty.map(|u| (u * factor).abs()),
+        }
+    }
+}
+
+impl Mul for Uncertain {
+    type Output = Self;
+
+    fn mul(self, o: Self) -> Self {
+        Uncertain {
+            value: self.value * o.value,
+            uncertainty: self.combined(&o, o.value, self.value),
+        }
+    }
+}
+
+impl Div for Uncertain {
+    type Output = Self;
+
+    fn div(self, o: Self) -> Self {
+        Uncertain {
+            value: self.value / o.value,
+            uncertainty: self.combined(&o, 1.0 / o.value, self.value / (o.value * o.value)),
+        }
+    }
+}
+
+/// The absolute uncertainties of a difference add in quadrature
+impl Sub for Uncertain {
+    type Output = Self;
+
+    fn sub(self, o: Self) -> Self {
+        let uncertainty = match (self.uncertainty, o.uncertainty) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0.0).hypot(b.unwrap_or(0.0))),
+        };
+        Uncertain { value: self.value - o.value, uncertainty }
+    }
+}
+
+impl fmt::Display for Uncertain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.uncertainty, f.precision()) {
+            (Some(u), Some(p)) => write!(f, "{:.*} ± {:.*}", p, self.value, p, u),
+            (Some(u), None) => write!(f, "{} ± {}", self.value, u),
+            (None, Some(p)) => write!(f, "{:.*}", p, self.value),
+            (None, None) => write!(f, "{}", self.value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::double_comparable;
+    const TOL: f64 = 1e-9;
+
+    #[test]
+    fn scaling_and_quadrature() {
+        let conc = Uncertain::new(10.0, Some(1.0));
+        let vol = Uncertain::new(200.0, Some(20.0));
+        let cells = Uncertain::exact(4.0);
+
+        let scaled = conc * -1e3;
+        assert!(double_comparable(scaled.value, -1e4, TOL));
+        assert!(double_comparable(scaled.uncertainty.unwrap(), 1e3, TOL));
+
+        // two 10% relative uncertainties -> sqrt(2) * 10%
+        let product = conc * vol;
+        assert!(double_comparable(product.value, 2000.0, TOL));
+        assert!(double_comparable(product.uncertainty.unwrap(), 2000.0 * 0.1 * 2f64.sqrt(), TOL));
+
+        let quotient = conc / cells;
+        assert!(double_comparable(quotient.value, 2.5, TOL));
+        assert!(double_comparable(quotient.uncertainty.unwrap(), 0.25, TOL));
+
+        assert_eq!((Uncertain::exact(2.0) * Uncertain::exact(3.0)).uncertainty, None);
+    }
+
+    #[test]
+    fn zero_values() {
+        // a blank-subtracted value of 0 keeps a finite uncertainty
+        let zero = Uncertain::new(0.0, Some(0.5));
+        let vol = Uncertain::new(200.0, Some(20.0));
+
+        let product = zero * vol;
+        assert_eq!(product.value, 0.0);
+        assert!(double_comparable(product.uncertainty.unwrap(), 100.0, TOL));
+
+        let quotient = zero / Uncertain::new(4.0, Some(1.0));
+        assert_eq!(quotient.value, 0.0);
+        assert!(double_comparable(quotient.uncertainty.unwrap(), 0.125, TOL));
+
+        let product = Uncertain::new(10.0, Some(1.0)) * Uncertain::exact(0.0);
+        assert_eq!(product.uncertainty, Some(0.0));
+    }
+
+    #[test]
+    fn differences() {
+        let diff = Uncertain::new(10.0, Some(3.0)) - Uncertain::new(4.0, Some(4.0));
+        assert!(double_comparable(diff.value, 6.0, TOL));
+        assert!(double_comparable(diff.uncertainty.unwrap(), 5.0, TOL));
+
+        let diff = Uncertain::new(10.0, Some(3.0)) - Uncertain::exact(12.0);
+        assert!(double_comparable(diff.value, -2.0, TOL));
+        assert!(double_comparable(diff.uncertainty.unwrap(), 3.0, TOL));
+        assert_eq!((Uncertain::exact(2.0) - Uncertain::exact(3.0)).uncertainty, None);
+    }
+}
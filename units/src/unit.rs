@@ -0,0 +1,598 @@
+//! A compositional unit grammar: a unit string is broken into a numerator
+//! (optionally a product of two parts, as in "Ω·cm²") and any number of
+//! denominators, each made of an optional power-of-ten multiplier, an SI prefix,
+//! and a base quantity (e.g., "pg/day/10^3 cells").
+//! The base quantities are mass, amount, volume, time, cell count, enzyme
+//! activity, area, electrical resistance, and fold change, plus dimensionless ratios.
+//! Conversion factors are then derived from the parts instead of being listed per unit.
+use std::fmt;
+use std::str::FromStr;
+use std::ops::{Add, Sub};
+use serde::de::{self, Visitor, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use crate::{alias, SIError, UnitAliases, UnitType};
+
+/// The most denominators a single unit can have, e.g. "ng/day/10^6 cells" has two
+const MAX_DENOMINATORS: usize = 3;
+
+/// Exponents of each base quantity that make up a unit
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Dimension {
+    pub mass: i8,
+    pub amount: i8,
+    pub volume: i8,
+    pub time: i8,
+    pub cells: i8,
+    /// enzyme activity, as in U or IU
+    pub activity: i8,
+    pub length: i8,
+    pub resistance: i8,
+    /// fold change, a ratio to some reference, which isn't a percent or fraction of anything
+    pub fold: i8,
+}
+
+impl Dimension {
+    /// Exponent, name, base SI unit, and the exponent of the quantity in that unit
+    fn parts(&self) -> [(i8, &'static str, Base, i8); 9] {
+        [
+            (self.mass, "mass", Base::Gram, 1),
+            (self.amount, "amount", Base::Mole, 1),
+            (self.volume, "volume", Base::Liter, 1),
+            (self.time, "time", Base::Second, 1),
+            (self.cells, "cells", Base::Cell, 1),
+            (self.activity, "activity", Base::EnzymeUnit, 1),
+            (self.resistance, "resistance", Base::Ohm, 1),
+            (self.length, "length", Base::SquareMeter, 2),
+            (self.fold, "fold change", Base::FoldChange, 1),
+        ]
+    }
+
+    /// The unprefixed SI unit for this dimension, e.g. g/L for mass/volume, or "fraction"
+    /// for a dimensionless ratio. None is returned if the dimension can't be written
+    /// as one or two quantities over others.
+    pub fn si_base(&self) -> Option<Unit> {
+        let term = |base| Term { scale: 0, prefix: Prefix::Unity, base };
+        let parts = self.parts();
+
+        let mut numer = parts.iter().filter(|(e, ..)| *e > 0);
+        let (numer, times) = match (numer.next(), numer.next(), numer.next()) {
+            (None, None, None) if *self == Dimension::default() => (term(Base::Fraction), None),
+            (Some(&(e, _, base, per)), None, None) if e == per => (term(base), None),
+            (Some(&(e1, _, b1, per1)), Some(&(e2, _, b2, per2)), None) if e1 == per1 && e2 == per2 =>
+                (term(b1), Some(term(b2))),
+            _ => return None,
+        };
+
+        let mut denom = [None; MAX_DENOMINATORS];
+        for (i, &(e, _, base, per)) in parts.iter().filter(|(e, ..)| *e < 0).enumerate() {
+            if e != -per { return None; }
+            *denom.get_mut(i)? = Some(term(base));
+        }
+
+        Some(Unit { numer, times, denom })
+    }
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts = self.parts();
+        let power = |e: i8| if e.abs() > 1 { format!("^{}", e.abs()) } else { String::new() };
+
+        let numer: Vec<_> = parts.iter()
+            .filter(|(e, ..)| *e > 0)
+            .map(|(e, name, ..)| format!("{}{}", name, power(*e)))
+            .collect();
+        let denom: Vec<_> = parts.iter()
+            .filter(|(e, ..)| *e < 0)
+            .map(|(e, name, ..)| format!("{}{}", name, power(*e)))
+            .collect();
+
+        match (numer.is_empty(), denom.is_empty()) {
+            (true, true) => write!(f, "dimensionless"),
+            (true, false) => write!(f, "1/{}", denom.join("/")),
+            (false, _) => {
+                write!(f, "{}", numer.join("·"))?;
+                denom.iter().try_for_each(|d| write!(f, "/{}", d))
+            }
+        }
+    }
+}
+
+impl Add for Dimension {
+    type Output = Self;
+
+    fn add(self, o: Self) -> Self {
+        Dimension {
+            mass: self.mass + o.mass,
+            amount: self.amount + o.amount,
+            volume: self.volume + o.volume,
+            time: self.time + o.time,
+            cells: self.cells + o.cells,
+            activity: self.activity + o.activity,
+            length: self.length + o.length,
+            resistance: self.resistance + o.resistance,
+            fold: self.fold + o.fold,
+        }
+    }
+}
+
+impl Sub for Dimension {
+    type Output = Self;
+
+    fn sub(self, o: Self) -> Self {
+        Dimension {
+            mass: self.mass - o.mass,
+            amount: self.amount - o.amount,
+            volume: self.volume - o.volume,
+            time: self.time - o.time,
+            cells: self.cells - o.cells,
+            activity: self.activity - o.activity,
+            length: self.length - o.length,
+            resistance: self.resistance - o.resistance,
+            fold: self.fold - o.fold,
+        }
+    }
+}
+
+/// SI prefixes that can be attached to a `Base`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Prefix {
+    Femto,
+    Pico,
+    Nano,
+    Micro,
+    Milli,
+    Centi,
+    Deci,
+    Unity,
+    Kilo,
+}
+
+impl Prefix {
+    fn factor(self) -> f64 {
+        use self::Prefix::*;
+
+        match self {
+            Femto => 1e-15,
+            Pico => 1e-12,
+            Nano => 1e-9,
+            Micro => 1e-6,
+            Milli => 1e-3,
+            Centi => 1e-2,
+            Deci => 1e-1,
+            Unity => 1.0,
+            Kilo => 1e3,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        use self::Prefix::*;
+
+        match self {
+            Femto => "f",
+            Pico => "p",
+            Nano => "n",
+            Micro => "µ",
+            Milli => "m",
+            Centi => "c",
+            Deci => "d",
+            Unity => "",
+            Kilo => "k",
+        }
+    }
+
+    /// Split a leading prefix off of `s`
+    fn split(s: &str) -> Option<(Self, &str)> {
+        use self::Prefix::*;
+
+        let mut chars = s.chars();
+        let prefix = match chars.next()? {
+            'f' => Femto,
+            'p' => Pico,
+            'n' => Nano,
+            'u' | 'µ' | 'μ' => Micro,
+            'm' => Milli,
+            'c' => Centi,
+            'd' => Deci,
+            'k' => Kilo,
+            _ => return None,
+        };
+
+        Some((prefix, chars.as_str()))
+    }
+}
+
+/// Base quantities that make up a unit
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Base {
+    Gram,
+    Mole,
+    Liter,
+    /// mol/L
+    Molar,
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Cell,
+    /// enzyme unit, 1 µmol of substrate per minute; the same size as an international unit (IU)
+    EnzymeUnit,
+    InternationalUnit,
+    /// m², so a prefix is applied twice (1 cm² = 10^-4 m²)
+    SquareMeter,
+    Ohm,
+    Percent,
+    Fraction,
+    FoldChange,
+}
+
+impl Base {
+    fn dimension(self) -> Dimension {
+        use self::Base::*;
+
+        let none = Dimension::default();
+        match self {
+            Gram => Dimension { mass: 1, ..none },
+            Mole => Dimension { amount: 1, ..none },
+            Liter => Dimension { volume: 1, ..none },
+            Molar => Dimension { amount: 1, volume: -1, ..none },
+            Second | Minute | Hour | Day => Dimension { time: 1, ..none },
+            Cell => Dimension { cells: 1, ..none },
+            EnzymeUnit | InternationalUnit => Dimension { activity: 1, ..none },
+            SquareMeter => Dimension { length: 2, ..none },
+            Ohm => Dimension { resistance: 1, ..none },
+            Percent | Fraction => none,
+            FoldChange => Dimension { fold: 1, ..none },
+        }
+    }
+
+    /// Factor to put this base into base SI units
+    fn factor(self) -> f64 {
+        use self::Base::*;
+
+        match self {
+            Minute => 60.0,
+            Hour => 60.0 * 60.0,
+            Day => 24.0 * 60.0 * 60.0,
+            Percent => 0.01,
+            _ => 1.0,
+        }
+    }
+
+    fn takes_prefix(self) -> bool {
+        use self::Base::*;
+
+        match self {
+            Gram | Mole | Liter | Molar | Second
+            | EnzymeUnit | InternationalUnit | SquareMeter | Ohm => true,
+            Minute | Hour | Day | Cell | Percent | Fraction | FoldChange => false,
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        use self::Base::*;
+
+        match s {
+            "g" => Some(Gram),
+            "mol" => Some(Mole),
+            "L" | "l" => Some(Liter),
+            "M" => Some(Molar),
+            "s" | "sec" => Some(Second),
+            "min" | "mins" | "minute" | "minutes" => Some(Minute),
+            "h" | "hr" | "hour" | "hours" => Some(Hour),
+            "d" | "day" | "days" => Some(Day),
+            "cell" | "cells" => Some(Cell),
+            "U" => Some(EnzymeUnit),
+            "IU" => Some(InternationalUnit),
+            "m²" | "m^2" | "m2" => Some(SquareMeter),
+            "Ω" | "\u{2126}" | "ohm" | "ohms" | "Ohm" | "Ohms" => Some(Ohm),
+            "%" | "percent" => Some(Percent),
+            "fraction" => Some(Fraction),
+            "fold" | "fold change" | "fold-change" | "foldchange" => Some(FoldChange),
+            _ => None,
+        }
+    }
+}
+
+/// One part of a unit, e.g. "µg" or "10^6 cells"
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Term {
+    /// power-of-ten multiplier, as in "10^6 cells"
+    scale: i8,
+    prefix: Prefix,
+    base: Base,
+}
+
+impl Term {
+    fn factor(&self) -> f64 {
+        let prefix = match self.base {
+            Base::SquareMeter => self.prefix.factor().powi(2),
+            _ => self.prefix.factor(),
+        };
+
+        10f64.powi(i32::from(self.scale)) * prefix * self.base.factor()
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, denominator: bool) -> fmt::Result {
+        if self.scale != 0 {
+            write!(f, "10^{} ", self.scale)?;
+        }
+        let base = match self.base {
+            Base::Gram => "g",
+            Base::Mole => "mol",
+            Base::Liter => "L",
+            Base::Molar => "M",
+            Base::Second => "s",
+            Base::Minute => "min",
+            Base::Hour => "hour",
+            Base::Day => "day",
+            Base::Cell if denominator && self.scale == 0 => "cell",
+            Base::Cell => "cells",
+            Base::EnzymeUnit => "U",
+            Base::InternationalUnit => "IU",
+            Base::SquareMeter => "m²",
+            Base::Ohm => "Ω",
+            Base::Percent => "%",
+            Base::Fraction => "fraction",
+            Base::FoldChange => "fold change",
+        };
+
+        write!(f, "{}{}", self.prefix.as_str(), base)
+    }
+}
+
+impl FromStr for Term {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scale, s) = split_scale(s.trim())?;
+        let (prefix, base) = match Base::parse(s) {
+            Some(base) => (Prefix::Unity, base),
+            None => {
+                let (prefix, rest) = Prefix::split(s).ok_or(())?;
+                let base = Base::parse(rest).filter(|b| b.takes_prefix()).ok_or(())?;
+                (prefix, base)
+            }
+        };
+
+        Ok(Term { scale, prefix, base })
+    }
+}
+
+/// Split a leading power-of-ten multiplier, like "10^6", "1e6", or "million", off of `s`
+fn split_scale(s: &str) -> Result<(i8, &str), ()> {
+    const WORDS: [(&str, i8); 3] = [("thousand", 3), ("million", 6), ("billion", 9)];
+
+    if let Some((word, scale)) = WORDS.iter().find(|(w, _)| s.starts_with(w)) {
+        return Ok((*scale, s[word.len()..].trim_start()));
+    }
+
+    let exponent = match s.strip_prefix("10^")
+        .or_else(|| s.strip_prefix("1e"))
+        .or_else(|| s.strip_prefix("1E"))
+    {
+        Some(e) => e,
+        None => return Ok((0, s)),
+    };
+    let digits = exponent
+        .find(|c: char| !(c.is_ascii_digit() || c == '-'))
+        .unwrap_or(exponent.len());
+    let scale = exponent[..digits].parse().map_err(|_| ())?;
+
+    Ok((scale, exponent[digits..].trim_start()))
+}
+
+/// A unit made up of a numerator, an optional second numerator part it's multiplied by,
+/// and up to three denominators, e.g. "ng/day/10^6 cells" or "Ω·cm²"
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Unit {
+    numer: Term,
+    times: Option<Term>,
+    denom: [Option<Term>; MAX_DENOMINATORS],
+}
+
+impl Unit {
+    fn parse_grammar(s: &str) -> Option<Self> {
+        let mut parts = s.split('/');
+        let mut factors = parts.next()?.split(['·', '⋅', '*']);
+        let numer = factors.next()?.parse().ok()?;
+        let times = match factors.next() {
+            Some(t) => Some(t.parse().ok()?),
+            None => None,
+        };
+        if factors.next().is_some() { return None; }
+
+        let mut denom = [None; MAX_DENOMINATORS];
+        for (i, part) in parts.enumerate() {
+            *denom.get_mut(i)? = Some(part.parse().ok()?);
+        }
+
+        Some(Unit { numer, times, denom })
+    }
+
+    fn terms(&self) -> impl Iterator<Item = &Term> {
+        self.denom.iter().filter_map(Option::as_ref)
+    }
+
+    /// The exponents of the base quantities in this unit
+    pub fn dimension(&self) -> Dimension {
+        let numer = self.times.iter()
+            .fold(self.numer.base.dimension(), |dim, t| dim + t.base.dimension());
+        self.terms()
+            .fold(numer, |dim, t| dim - t.base.dimension())
+    }
+
+    pub fn unit_type(&self) -> UnitType {
+        UnitType::from(self.dimension())
+    }
+
+    /// Factor to put this unit into base SI unit
+    pub fn si_factor(&self) -> f64 {
+        let numer = self.times.iter()
+            .fold(self.numer.factor(), |fact, t| fact * t.factor());
+        self.terms()
+            .fold(numer, |fact, t| fact / t.factor())
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.numer.write(f, false)?;
+        if let Some(t) = self.times.as_ref() {
+            write!(f, "·")?;
+            t.write(f, false)?;
+        }
+        for t in self.terms() {
+            write!(f, "/")?;
+            t.write(f, true)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Unit {
+    /// Parse the string as written, then through `aliases`, then with case and whitespace ignored
+    pub fn parse_with(s: &str, aliases: &UnitAliases) -> Result<Self, SIError> {
+        Unit::parse_grammar(s)
+            .or_else(|| aliases.resolve(s).and_then(|c| Unit::parse_grammar(&c)))
+            .or_else(|| Unit::parse_grammar(&alias::normalize(s)))
+            .ok_or_else(|| SIError::UnkType(s.to_string()))
+    }
+}
+
+impl FromStr for Unit {
+    type Err = SIError;
+
+    /// Parse the string with the process-wide alias registry
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        alias::with_registry(|aliases| Unit::parse_with(s, aliases))
+    }
+}
+
+impl Serialize for Unit {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+    {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Unit {
+    fn deserialize<D>(d: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de>
+    {
+        d.deserialize_str(UnitVisitor)
+    }
+}
+struct UnitVisitor;
+
+impl<'de> Visitor<'de> for UnitVisitor {
+    type Value = Unit;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "A unit made of SI-prefixed mass, amount, volume, time, cell count, activity, area, or resistance parts")
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+    where E: de::Error
+    {
+        Self::Value::from_str(s)
+            .map_err( |e| E::custom(format!("{}",e)) )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        let cases = [
+            ("µg/mL", "µg/mL"),
+            ("ug/ml", "µg/mL"),
+            ("fg/mL", "fg/mL"),
+            ("ng/µL", "ng/µL"),
+            ("nmol/L", "nmol/L"),
+            ("µM", "µM"),
+            ("ng / mL", "ng/mL"),
+            ("pg/day/10^3 cells", "pg/day/10^3 cells"),
+            ("ng/day/10^6cells", "ng/day/10^6 cells"),
+            ("ng/day/cell", "ng/day/cell"),
+            ("kg", "kg"),
+            ("ng/hr", "ng/hour"),
+            ("µg/min/10^6 cells", "µg/min/10^6 cells"),
+            ("ms", "ms"),
+            ("pg/day/10^3cells", "pg/day/10^3 cells"),
+            ("ng/day/1e6 cells", "ng/day/10^6 cells"),
+            ("ng/day/million cells", "ng/day/10^6 cells"),
+            ("ng/day/10^6 cell", "ng/day/10^6 cells"),
+            ("μg/mL", "µg/mL"),
+            ("NG/ML", "ng/mL"),
+            ("mcg/mL", "µg/mL"),
+            ("UM", "µM"),
+            ("mM", "mM"),
+            ("U/L", "U/L"),
+            ("mU/mL", "mU/mL"),
+            ("mIU/mL", "mIU/mL"),
+            ("cells/mL", "cells/mL"),
+            ("10^6 cells/mL", "10^6 cells/mL"),
+            ("Ω·cm²", "Ω·cm²"),
+            ("ohm*cm^2", "Ω·cm²"),
+            ("kΩ·cm2", "kΩ·cm²"),
+            ("%", "%"),
+            ("percent", "%"),
+            ("fold-change", "fold change"),
+            ("fraction", "fraction"),
+        ];
+
+        for (input, expected) in cases.iter() {
+            let unit: Unit = input.parse().unwrap();
+            assert_eq!(&unit.to_string(), expected, "parsing {}", input);
+        }
+    }
+
+    #[test]
+    fn unknown_units() {
+        for bad in ["", "xg/mL", "ng/", "mday", "khour", "kcells", "g/L/day/cell/mL", "10^x cells"].iter() {
+            assert!(bad.parse::<Unit>().is_err(), "{} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn dimensions() {
+        let none = Dimension::default();
+        let conc: Unit = "µg/mL".parse().unwrap();
+        let molar: Unit = "mM".parse().unwrap();
+        let per_cell: Unit = "pg/day/10^3 cells".parse().unwrap();
+
+        assert_eq!(conc.dimension(), Dimension { mass: 1, volume: -1, ..none });
+        assert_eq!(molar.dimension(), "mmol/L".parse::<Unit>().unwrap().dimension());
+        assert_eq!(per_cell.dimension(), Dimension { mass: 1, time: -1, cells: -1, ..none });
+        assert_eq!(per_cell.unit_type(), UnitType::CellNormalized);
+
+        let teer: Unit = "Ω·cm²".parse().unwrap();
+        let fold: Unit = "fold change".parse().unwrap();
+        assert_eq!(teer.dimension(), Dimension { resistance: 1, length: 2, ..none });
+        assert_eq!(fold.dimension(), Dimension { fold: 1, ..none });
+        assert_eq!(fold.unit_type(), UnitType::FoldChange);
+        assert_eq!("%".parse::<Unit>().unwrap().dimension(), none);
+        assert_eq!("U/L".parse::<Unit>().unwrap().unit_type(), UnitType::EnzymeActivity);
+        assert_eq!("cells/mL".parse::<Unit>().unwrap().unit_type(), UnitType::CellDensity);
+    }
+
+    #[test]
+    fn si_base_units() {
+        let base = |u: &str| u.parse::<Unit>().unwrap().dimension().si_base().map(|b| b.to_string());
+
+        assert_eq!(base("µg/mL").as_deref(), Some("g/L"));
+        assert_eq!(base("mM").as_deref(), Some("mol/L"));
+        assert_eq!(base("ng/day/10^6 cells").as_deref(), Some("g/s/cell"));
+        assert_eq!(base("hour").as_deref(), Some("s"));
+        assert_eq!(base("mU/mL").as_deref(), Some("U/L"));
+        assert_eq!(base("Ω·cm²").as_deref(), Some("Ω·m²"));
+        assert_eq!(base("%").as_deref(), Some("fraction"));
+        assert_eq!(Dimension { mass: 2, ..Dimension::default() }.si_base(), None);
+        assert_eq!(Dimension { mass: 1, volume: -1, ..Dimension::default() }.to_string(), "mass/volume");
+    }
+}